@@ -1,25 +1,133 @@
 use crate::*;
+use std::path::{Path, PathBuf};
 
-fn desugar_datatype(name: Symbol, variants: Vec<Variant>) -> Vec<NCommand> {
+fn desugar_datatype(
+    desugar: &mut Desugar,
+    name: Symbol,
+    variants: Vec<Variant>,
+    discriminators: bool,
+    merge: Option<Expr>,
+) -> Vec<NCommand> {
+    let discriminator_commands = if discriminators {
+        variants
+            .iter()
+            .flat_map(|variant| desugar_discriminator(desugar, name, variant))
+            .collect()
+    } else {
+        vec![]
+    };
+    let accessor_commands: Vec<NCommand> = variants
+        .iter()
+        .flat_map(|variant| desugar_accessors(desugar, name, variant))
+        .collect();
     vec![NCommand::Sort(name, None)]
         .into_iter()
         .chain(variants.into_iter().map(|variant| {
+            // field names don't affect the constructor, keep them for later passes
+            desugar
+                .constructor_fields
+                .insert(variant.name, variant.fields);
+            if merge.is_some() {
+                desugar.merged_constructors.insert(variant.name);
+            }
             NCommand::Function(FunctionDecl {
                 name: variant.name,
                 schema: Schema {
                     input: variant.types,
                     output: name,
                 },
-                merge: None,
+                merge: merge.clone(),
                 merge_action: vec![],
                 default: None,
                 cost: variant.cost,
                 unextractable: false,
             })
         }))
+        .chain(discriminator_commands)
+        .chain(accessor_commands)
         .collect()
 }
 
+/// Declares a function `Ctor-field` for each named field of the constructor `Ctor`,
+/// with a rule projecting the field out of every value the constructor builds.
+/// A name that is already taken fails typechecking like any other redeclaration.
+fn desugar_accessors(desugar: &mut Desugar, sort: Symbol, variant: &Variant) -> Vec<NCommand> {
+    let mut res = vec![];
+    for (i, field) in variant.fields.iter().enumerate() {
+        let Some(field) = field else {
+            continue;
+        };
+        let accessor = Symbol::from(format!("{}-{}", variant.name, field));
+        let var = desugar.get_fresh();
+        let args: Vec<Symbol> = variant.types.iter().map(|_| desugar.get_fresh()).collect();
+        let rule = Rule {
+            body: vec![Fact::Eq(vec![
+                Expr::Var(DUMMY_SPAN, var),
+                Expr::Call(
+                    DUMMY_SPAN,
+                    variant.name,
+                    args.iter().map(|arg| Expr::Var(DUMMY_SPAN, *arg)).collect(),
+                ),
+            ])],
+            head: vec![Action::Set(
+                accessor,
+                vec![Expr::Var(DUMMY_SPAN, var)],
+                Expr::Var(DUMMY_SPAN, args[i]),
+            )],
+        };
+        res.push(NCommand::Function(FunctionDecl {
+            name: accessor,
+            schema: Schema {
+                input: vec![sort],
+                output: variant.types[i],
+            },
+            merge: None,
+            merge_action: vec![],
+            default: None,
+            cost: None,
+            // the field itself is always a better term
+            unextractable: true,
+        }));
+        res.push(NCommand::NormRule {
+            ruleset: "".into(),
+            name: rule.to_string().replace('\"', "'").into(),
+            rule: flatten_rule(rule, desugar),
+        });
+    }
+    res
+}
+
+/// Declares the relation `Ctor?` and a rule filling it in with
+/// every value built by the constructor `Ctor`.
+fn desugar_discriminator(desugar: &mut Desugar, sort: Symbol, variant: &Variant) -> Vec<NCommand> {
+    let discriminator = Symbol::from(format!("{}?", variant.name));
+    let var = desugar.get_fresh();
+    let args = variant
+        .types
+        .iter()
+        .map(|_| Expr::Var(DUMMY_SPAN, desugar.get_fresh()))
+        .collect();
+    let rule = Rule {
+        body: vec![Fact::Eq(vec![
+            Expr::Var(DUMMY_SPAN, var),
+            Expr::Call(DUMMY_SPAN, variant.name, args),
+        ])],
+        head: vec![Action::Expr(Expr::Call(
+            DUMMY_SPAN,
+            discriminator,
+            vec![Expr::Var(DUMMY_SPAN, var)],
+        ))],
+    };
+    vec![
+        NCommand::Function(FunctionDecl::relation(discriminator, vec![sort])),
+        NCommand::NormRule {
+            ruleset: "".into(),
+            name: rule.to_string().replace('\"', "'").into(),
+            rule: flatten_rule(rule, desugar),
+        },
+    ]
+}
+
 fn desugar_rewrite(
     ruleset: Symbol,
     name: Symbol,
@@ -35,11 +143,17 @@ fn desugar_rewrite(
         name,
         rule: flatten_rule(
             Rule {
-                body: [Fact::Eq(vec![Expr::Var(var), rewrite.lhs.clone()])]
-                    .into_iter()
-                    .chain(rewrite.conditions.clone())
-                    .collect(),
-                head: vec![Action::Union(Expr::Var(var), rewrite.rhs.clone())],
+                body: [Fact::Eq(vec![
+                    Expr::Var(DUMMY_SPAN, var),
+                    rewrite.lhs.clone(),
+                ])]
+                .into_iter()
+                .chain(rewrite.conditions.clone())
+                .collect(),
+                head: vec![Action::Union(
+                    Expr::Var(DUMMY_SPAN, var),
+                    rewrite.rhs.clone(),
+                )],
             },
             desugar,
         ),
@@ -50,14 +164,20 @@ fn desugar_birewrite(
     ruleset: Symbol,
     name: Symbol,
     rewrite: &Rewrite,
+    directed: &DirectedConditions,
     desugar: &mut Desugar,
 ) -> Vec<NCommand> {
+    let rw1 = Rewrite {
+        lhs: rewrite.lhs.clone(),
+        rhs: rewrite.rhs.clone(),
+        conditions: [rewrite.conditions.clone(), directed.forward.clone()].concat(),
+    };
     let rw2 = Rewrite {
         lhs: rewrite.rhs.clone(),
         rhs: rewrite.lhs.clone(),
-        conditions: rewrite.conditions.clone(),
+        conditions: [rewrite.conditions.clone(), directed.backward.clone()].concat(),
     };
-    desugar_rewrite(ruleset, format!("{}=>", name).into(), rewrite, desugar)
+    desugar_rewrite(ruleset, format!("{}=>", name).into(), &rw1, desugar)
         .into_iter()
         .chain(desugar_rewrite(
             ruleset,
@@ -90,7 +210,7 @@ fn normalize_expr(
         return;
     }
 
-    if let Expr::Var(v) = expr {
+    if let Expr::Var(_, v) = expr {
         if *v == lhs_in {
             return;
         }
@@ -120,16 +240,16 @@ fn normalize_expr(
     };
 
     match expr {
-        Expr::Lit(l) => res.push(NormFact::AssignLit(lhs, l.clone())),
-        Expr::Var(_v) => {
+        Expr::Lit(_, l) => res.push(NormFact::AssignLit(lhs, l.clone())),
+        Expr::Var(..) => {
             panic!("handled above");
         }
-        Expr::Call(f, children) => {
+        Expr::Call(_, f, children) => {
             let is_compute = desugar.type_info.is_primitive(*f);
             let mut new_children = vec![];
             for child in children {
                 match child {
-                    Expr::Var(v) => {
+                    Expr::Var(_, v) => {
                         if is_compute {
                             if !is_bound(*v, desugar, bound) {
                                 panic!("Unbound variable {v} in primitive computation");
@@ -192,15 +312,16 @@ fn flatten_equalities(equalities: Vec<(Symbol, Expr)>, desugar: &mut Desugar) ->
 
 fn flatten_facts(facts: &Vec<Fact>, desugar: &mut Desugar) -> Vec<NormFact> {
     let mut equalities = vec![];
+    let mut absent = vec![];
     for fact in facts {
         match fact {
             Fact::Eq(args) => {
                 assert!(args.len() == 2);
                 let lhs = &args[0];
                 let rhs = &args[1];
-                if let Expr::Var(v) = lhs {
+                if let Expr::Var(_, v) = lhs {
                     equalities.push((*v, rhs.clone()));
-                } else if let Expr::Var(v) = rhs {
+                } else if let Expr::Var(_, v) = rhs {
                     equalities.push((*v, lhs.clone()));
                 } else {
                     let fresh = desugar.get_fresh();
@@ -211,21 +332,68 @@ fn flatten_facts(facts: &Vec<Fact>, desugar: &mut Desugar) -> Vec<NormFact> {
             Fact::Fact(expr) => {
                 // we can drop facts that are
                 // just a variable
-                if let Expr::Var(_v) = expr {
+                if let Expr::Var(..) = expr {
                 } else {
                     equalities.push((desugar.get_fresh(), expr.clone()));
                 }
             }
+            Fact::Absent(expr) => {
+                let Expr::Call(_, f, args) = expr else {
+                    panic!("Expected a call in absent, got {expr}");
+                };
+                // non-variable arguments are matched like any other fact
+                let args = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Expr::Var(_, v) => *v,
+                        _ => {
+                            let fresh = desugar.get_fresh();
+                            equalities.push((fresh, arg.clone()));
+                            fresh
+                        }
+                    })
+                    .collect();
+                absent.push(NormFact::Absent(NormExpr::Call(*f, args)));
+            }
         }
     }
 
-    flatten_equalities(equalities, desugar)
+    let mut res = flatten_equalities(equalities, desugar);
+    res.extend(absent);
+    res
 }
 
 fn flatten_actions(actions: &Vec<Action>, desugar: &mut Desugar) -> Vec<NormAction> {
-    let mut memo = Default::default();
+    flatten_actions_with_memo(actions, desugar, &mut Default::default())
+}
+
+/// Flattens top-level actions, reusing the bindings of earlier commands when
+/// [`Desugar::session_memo`] is enabled.
+fn flatten_global_actions(actions: &Vec<Action>, desugar: &mut Desugar) -> Vec<NormAction> {
+    let Some(mut memo) = desugar.session_memo.take() else {
+        return flatten_actions(actions, desugar);
+    };
+    let res = flatten_actions_with_memo(actions, desugar, &mut memo);
+    if actions
+        .iter()
+        .any(|action| matches!(action, Action::Delete(..)))
+    {
+        // a deleted row has to be added again by the next command that mentions it
+        memo.clear();
+    }
+    // other functions can be set later, so only constructor terms keep their value
+    memo.retain(|expr, _| desugar.is_constructor_term(expr));
+    desugar.session_memo = Some(memo);
+    res
+}
+
+fn flatten_actions_with_memo(
+    actions: &Vec<Action>,
+    desugar: &mut Desugar,
+    memo: &mut HashMap<Expr, Symbol>,
+) -> Vec<NormAction> {
     let mut add_expr = |expr: Expr, res: &mut Vec<NormAction>| -> Symbol {
-        desugar.expr_to_flat_actions(&expr, res, &mut memo)
+        desugar.expr_to_flat_actions(&expr, res, memo)
     };
 
     let mut res = vec![];
@@ -267,6 +435,17 @@ fn flatten_actions(actions: &Vec<Action>, desugar: &mut Desugar) -> Vec<NormActi
                 ));
                 res.push(del);
             }
+            Action::Subsume(symbol, exprs) => {
+                let subsume = NormAction::Subsume(NormExpr::Call(
+                    *symbol,
+                    exprs
+                        .clone()
+                        .into_iter()
+                        .map(|ex| add_expr(ex, &mut res))
+                        .collect(),
+                ));
+                res.push(subsume);
+            }
             Action::Union(lhs, rhs) => {
                 let un = NormAction::Union(
                     add_expr(lhs.clone(), &mut res),
@@ -274,6 +453,10 @@ fn flatten_actions(actions: &Vec<Action>, desugar: &mut Desugar) -> Vec<NormActi
                 );
                 res.push(un);
             }
+            Action::Pin(expr) => {
+                let pin = NormAction::Pin(add_expr(expr.clone(), &mut res));
+                res.push(pin);
+            }
             Action::Panic(msg) => {
                 res.push(NormAction::Panic(msg.clone()));
             }
@@ -341,6 +524,10 @@ fn desugar_schedule(desugar: &mut Desugar, schedule: &Schedule) -> NormSchedule
             let norm_schedule = desugar_schedule(desugar, schedule);
             NormSchedule::Saturate(Box::new(norm_schedule))
         }
+        Schedule::Timeout(duration, schedule) => {
+            let norm_schedule = desugar_schedule(desugar, schedule);
+            NormSchedule::Timeout(*duration, Box::new(norm_schedule))
+        }
         Schedule::Run(run_config) => {
             let norm_run_config = desugar_run_config(desugar, run_config);
             NormSchedule::Run(norm_run_config)
@@ -356,10 +543,23 @@ fn desugar_schedule(desugar: &mut Desugar, schedule: &Schedule) -> NormSchedule
 }
 
 fn desugar_run_config(desugar: &mut Desugar, run_config: &RunConfig) -> NormRunConfig {
-    let RunConfig { ruleset, until } = run_config;
+    let RunConfig {
+        ruleset,
+        until,
+        until_mode,
+        dry_run,
+    } = run_config;
     NormRunConfig {
         ruleset: *ruleset,
-        until: until.clone().map(|facts| flatten_facts(&facts, desugar)),
+        until: until.as_ref().map(|facts| match until_mode {
+            UntilMode::All => vec![flatten_facts(facts, desugar)],
+            UntilMode::Any => facts
+                .iter()
+                .map(|fact| flatten_facts(&vec![fact.clone()], desugar))
+                .collect(),
+        }),
+        until_mode: *until_mode,
+        dry_run: *dry_run,
     }
 }
 
@@ -372,25 +572,39 @@ fn add_semi_naive_rule(desugar: &mut Desugar, rule: Rule) -> Option<Rule> {
     let mut add_new_rule = false;
 
     let mut var_set = HashSet::default();
+    // function calls already moved to the body, so repeated calls share a variable
+    let mut lifted_calls: HashMap<Expr, Symbol> = HashMap::default();
     for head_slice in new_rule.head.iter_mut().rev() {
         match head_slice {
             Action::Set(_, _, expr) => {
                 var_set.extend(expr.vars());
-                if let Expr::Call(_, _) = expr {
-                    add_new_rule = true;
-
-                    let fresh_symbol = desugar.get_fresh();
-                    let fresh_var = Expr::Var(fresh_symbol);
-                    let expr = std::mem::replace(expr, fresh_var.clone());
-                    new_head_atoms.push(Fact::Eq(vec![fresh_var, expr]));
-                };
+                // every function call in the value is moved to the body, even below
+                // primitives like `set-insert`, so a change to any of them triggers the rule
+                let mut lifted = vec![];
+                *expr = expr.map(&mut |e| match e {
+                    Expr::Call(_, f, _) if !desugar.type_info.is_primitive(*f) => {
+                        let var = *lifted_calls.entry(e.clone()).or_insert_with(|| {
+                            let fresh_symbol = desugar.get_fresh();
+                            lifted.push(Fact::Eq(vec![
+                                Expr::Var(DUMMY_SPAN, fresh_symbol),
+                                e.clone(),
+                            ]));
+                            fresh_symbol
+                        });
+                        Expr::Var(e.span(), var)
+                    }
+                    _ => e.clone(),
+                });
+                add_new_rule |= !lifted.is_empty();
+                // the atoms are reversed below, so inner calls end up first
+                new_head_atoms.extend(lifted.into_iter().rev());
             }
             Action::Let(symbol, expr) if var_set.contains(symbol) => {
                 var_set.extend(expr.vars());
-                if let Expr::Call(_, _) = expr {
+                if let Expr::Call(..) = expr {
                     add_new_rule = true;
 
-                    let var = Expr::Var(*symbol);
+                    let var = Expr::Var(DUMMY_SPAN, *symbol);
                     new_head_atoms.push(Fact::Eq(vec![var, expr.clone()]));
                 }
             }
@@ -417,8 +631,25 @@ pub struct Desugar {
     pub(crate) parser: ast::parse::ProgramParser,
     // TODO fix getting fresh names using modules
     pub(crate) number_underscores: usize,
+    // fresh names are this prefix, a number, and the underscores
+    pub(crate) fresh_prefix: String,
     pub(crate) global_variables: HashSet<Symbol>,
+    // field names of datatype constructors, by constructor name
+    pub(crate) constructor_fields: HashMap<Symbol, Vec<Option<Symbol>>>,
+    // constructors of datatypes declared with a shared `:merge`
+    pub(crate) merged_constructors: HashSet<Symbol>,
     pub(crate) type_info: TypeInfo,
+    // directories searched, in order, for included files
+    pub(crate) include_paths: Vec<PathBuf>,
+    // directories of the files being included, innermost last
+    include_stack: Vec<PathBuf>,
+    // canonical paths of the files included so far
+    included_files: HashSet<PathBuf>,
+    /// When `Some`, the bindings made while flattening top-level actions, so that
+    /// later commands reuse them for the same subexpressions instead of new variables.
+    pub(crate) session_memo: Option<HashMap<Expr, Symbol>>,
+    // the rule names used so far, with their rulesets
+    rule_names: HashSet<(Symbol, Symbol)>,
 }
 
 impl Default for Desugar {
@@ -429,8 +660,16 @@ impl Default for Desugar {
             // these come from lalrpop and don't have default impls
             parser: ast::parse::ProgramParser::new(),
             number_underscores: 3,
+            fresh_prefix: "v".into(),
             global_variables: Default::default(),
+            constructor_fields: Default::default(),
+            merged_constructors: Default::default(),
             type_info: TypeInfo::default(),
+            include_paths: Default::default(),
+            include_stack: Default::default(),
+            included_files: Default::default(),
+            session_memo: None,
+            rule_names: Default::default(),
         }
     }
 }
@@ -439,6 +678,7 @@ pub(crate) fn desugar_simplify(
     desugar: &mut Desugar,
     expr: &Expr,
     schedule: &Schedule,
+    variants: usize,
 ) -> Vec<NCommand> {
     let mut res = vec![NCommand::Push(1)];
     let lhs = desugar.get_fresh();
@@ -451,8 +691,8 @@ pub(crate) fn desugar_simplify(
     res.extend(
         desugar_command(
             Command::Extract {
-                variants: 0,
-                fact: Fact::Fact(Expr::Var(lhs)),
+                variants,
+                fact: Fact::Fact(Expr::Var(DUMMY_SPAN, lhs)),
             },
             desugar,
             false,
@@ -471,13 +711,18 @@ pub(crate) fn desugar_calc(
     desugar: &mut Desugar,
     idents: Vec<IdentSort>,
     exprs: Vec<Expr>,
+    ruleset: Symbol,
     seminaive_transform: bool,
 ) -> Result<Vec<NCommand>, Error> {
     let mut res = vec![];
 
     // first, push all the idents
     for IdentSort { ident, sort } in idents {
-        res.push(Command::Declare { name: ident, sort });
+        res.push(Command::Declare {
+            name: ident,
+            sort,
+            cost: None,
+        });
     }
 
     // now, for every pair of exprs we need to prove them equal
@@ -492,8 +737,10 @@ pub(crate) fn desugar_calc(
 
         res.push(Command::RunSchedule(Schedule::Saturate(Box::new(
             Schedule::Run(RunConfig {
-                ruleset: "".into(),
+                ruleset,
                 until: Some(vec![Fact::Eq(vec![expr1.clone(), expr2.clone()])]),
+                until_mode: UntilMode::All,
+                dry_run: false,
             }),
         ))));
 
@@ -509,8 +756,25 @@ pub(crate) fn desugar_calc(
         .map(|cmds| cmds.into_iter().map(|cmd| cmd.command).collect())
 }
 
-pub(crate) fn rewrite_name(rewrite: &Rewrite) -> String {
-    rewrite.to_string().replace('\"', "'")
+pub(crate) fn rewrite_name(
+    ruleset: Symbol,
+    rewrite: &Rewrite,
+    directed: Option<&DirectedConditions>,
+) -> String {
+    rewrite
+        .to_sexp(ruleset, directed)
+        .to_string()
+        .replace('\"', "'")
+}
+
+/// Whether the command runs rules outside of a scope of its own.
+/// `simplify`, `calc` and `check-in` run theirs between a push and a pop.
+fn runs_rules(command: &Command) -> bool {
+    match command {
+        Command::RunSchedule(_) | Command::Extract { .. } => true,
+        Command::Fail(command) => runs_rules(command),
+        _ => false,
+    }
 }
 
 pub(crate) fn desugar_command(
@@ -519,6 +783,13 @@ pub(crate) fn desugar_command(
     get_all_proofs: bool,
     seminaive_transform: bool,
 ) -> Result<Vec<NormCommand>, Error> {
+    if runs_rules(&command) {
+        // rules may delete the rows that the shared bindings stand for
+        if let Some(memo) = &mut desugar.session_memo {
+            memo.clear();
+        }
+    }
+    let mut rule_name = None;
     let res = match command {
         Command::SetOption { name, value } => {
             vec![NCommand::SetOption { name, value }]
@@ -526,23 +797,34 @@ pub(crate) fn desugar_command(
         Command::Function(fdecl) => {
             vec![NCommand::Function(fdecl)]
         }
-        Command::Declare { name, sort } => desugar.declare(name, sort),
-        Command::Datatype { name, variants } => desugar_datatype(name, variants),
+        Command::Declare { name, sort, cost } => desugar.declare(name, sort, cost),
+        Command::Datatype {
+            name,
+            variants,
+            discriminators,
+            merge,
+        } => desugar_datatype(desugar, name, variants, discriminators, merge),
         Command::Rewrite(ruleset, rewrite) => {
-            desugar_rewrite(ruleset, rewrite_name(&rewrite).into(), &rewrite, desugar)
+            let name = desugar.fresh_rule_name(ruleset, rewrite_name(ruleset, &rewrite, None));
+            rule_name = Some(name);
+            desugar_rewrite(ruleset, name, &rewrite, desugar)
         }
-        Command::BiRewrite(ruleset, rewrite) => {
-            desugar_birewrite(ruleset, rewrite_name(&rewrite).into(), &rewrite, desugar)
+        Command::BiRewrite(ruleset, rewrite, directed) => {
+            // keep the names of birewrites without directed conditions as they were
+            let name = rewrite_name(
+                ruleset,
+                &rewrite,
+                (!directed.is_empty()).then_some(&directed),
+            );
+            let name = desugar.fresh_rule_name(ruleset, name);
+            rule_name = Some(name);
+            desugar_birewrite(ruleset, name, &rewrite, &directed, desugar)
         }
         Command::Include(file) => {
-            let s = std::fs::read_to_string(&file)
-                .unwrap_or_else(|_| panic!("Failed to read file {file}"));
-            return desugar_commands(
-                desugar.parse_program(&s)?,
-                desugar,
-                get_all_proofs,
-                seminaive_transform,
-            );
+            return desugar_include(&file, false, desugar, get_all_proofs, seminaive_transform)
+        }
+        Command::IncludeAlways(file) => {
+            return desugar_include(&file, true, desugar, get_all_proofs, seminaive_transform)
         }
         Command::Rule {
             ruleset,
@@ -550,8 +832,11 @@ pub(crate) fn desugar_command(
             rule,
         } => {
             if name == "".into() {
-                name = rule.to_string().replace('\"', "'").into();
+                name = desugar.fresh_rule_name(ruleset, rule.to_string().replace('\"', "'"));
+            } else if !desugar.rule_names.insert((ruleset, name)) {
+                return Err(Error::DuplicateRuleName(ruleset, name));
             }
+            rule_name = Some(name);
 
             let mut result = vec![NCommand::NormRule {
                 ruleset,
@@ -574,30 +859,37 @@ pub(crate) fn desugar_command(
         Command::Sort(sort, option) => vec![NCommand::Sort(sort, option)],
         // TODO ignoring cost for now
         Command::AddRuleset(name) => vec![NCommand::AddRuleset(name)],
-        Command::Action(action) => flatten_actions(&vec![action], desugar)
+        Command::Action(action) => flatten_global_actions(&vec![action], desugar)
             .into_iter()
             .map(NCommand::NormAction)
             .collect(),
-        Command::Simplify { expr, schedule } => desugar_simplify(desugar, &expr, &schedule),
-        Command::Calc(idents, exprs) => desugar_calc(desugar, idents, exprs, seminaive_transform)?,
+        Command::Actions(actions) => {
+            vec![NCommand::NormActions(flatten_global_actions(
+                &actions, desugar,
+            ))]
+        }
+        Command::Simplify {
+            expr,
+            schedule,
+            variants,
+        } => desugar_simplify(desugar, &expr, &schedule, variants),
+        Command::Calc(idents, exprs, ruleset) => {
+            desugar_calc(desugar, idents, exprs, ruleset, seminaive_transform)?
+        }
         Command::RunSchedule(sched) => {
             vec![NCommand::RunSchedule(desugar_schedule(desugar, &sched))]
         }
-        // TODO add variants to extract action
-        Command::Extract {
-            variants: _variants,
-            fact,
-        } => {
+        Command::Extract { variants, fact } => {
             let fresh = desugar.get_fresh();
             let fresh_ruleset = desugar.get_fresh();
-            let desugaring = if let Fact::Fact(Expr::Var(v)) = fact {
-                format!("(extract {v})")
+            let desugaring = if let Fact::Fact(Expr::Var(_, v)) = fact {
+                format!("(extract {v} {variants})")
             } else {
                 format!(
                     "(check {fact})
                     (ruleset {fresh_ruleset})
                     (rule ((= {fresh} {fact}))
-                          ((extract {fresh}))
+                          ((extract {fresh} {variants}))
                           :ruleset {fresh_ruleset})
                     (run {fresh_ruleset} 1)"
                 )
@@ -622,10 +914,16 @@ pub(crate) fn desugar_command(
 
             res
         }
+        Command::CheckIn(config, facts) => vec![NCommand::CheckIn(
+            desugar_run_config(desugar, &config),
+            flatten_facts(&facts, desugar),
+        )],
         Command::CheckProof => vec![NCommand::CheckProof],
         Command::PrintTable(symbol, size) => vec![NCommand::PrintTable(symbol, size)],
+        Command::PrintBySort(sort, size) => vec![NCommand::PrintBySort(sort, size)],
         Command::PrintSize(symbol) => vec![NCommand::PrintSize(symbol)],
-        Command::Output { file, exprs } => vec![NCommand::Output { file, exprs }],
+        Command::PrintFingerprint => vec![NCommand::PrintFingerprint],
+        Command::Output { file, exprs, mode } => vec![NCommand::Output { file, exprs, mode }],
         Command::Push(num) => {
             vec![NCommand::Push(num)]
         }
@@ -633,7 +931,10 @@ pub(crate) fn desugar_command(
             vec![NCommand::Pop(num)]
         }
         Command::Fail(cmd) => {
+            // the failing command's bindings never get defined
+            let session_memo = desugar.session_memo.clone();
             let mut desugared = desugar_command(*cmd, desugar, false, seminaive_transform)?;
+            desugar.session_memo = session_memo;
 
             let last = desugared.pop().unwrap();
             desugared.push(NormCommand {
@@ -647,15 +948,13 @@ pub(crate) fn desugar_command(
         }
     };
 
-    for cmd in &res {
-        if let NCommand::NormAction(action) = cmd {
-            action.map_def_use(&mut |var, is_def| {
-                if is_def {
-                    desugar.global_variables.insert(var);
-                }
-                var
-            });
-        }
+    for action in res.iter().flat_map(|cmd| cmd.top_level_actions()) {
+        action.map_def_use(&mut |var, is_def| {
+            if is_def {
+                desugar.global_variables.insert(var);
+            }
+            var
+        });
     }
 
     Ok(res
@@ -663,12 +962,39 @@ pub(crate) fn desugar_command(
         .map(|c| NormCommand {
             metadata: Metadata {
                 id: desugar.get_new_id(),
+                rule_name,
             },
             command: c,
         })
         .collect())
 }
 
+fn desugar_include(
+    file: &str,
+    always: bool,
+    desugar: &mut Desugar,
+    get_all_proofs: bool,
+    seminaive_transform: bool,
+) -> Result<Vec<NormCommand>, Error> {
+    let path = desugar.resolve_include(file)?;
+    let canonical = std::fs::canonicalize(&path).map_err(|e| Error::IoError(path.clone(), e))?;
+    // like `#pragma once`, a file is only included the first time
+    // unless `include-always` is used
+    if desugar.included_files.contains(&canonical) && !always {
+        return Ok(vec![]);
+    }
+    let s = std::fs::read_to_string(&path).map_err(|e| Error::IoError(path.clone(), e))?;
+    let program = desugar.parse_file(Some(Symbol::from(&*path.to_string_lossy())), &s)?;
+    // only a file that was read and parsed counts as included
+    desugar.included_files.insert(canonical);
+    // includes inside the file are resolved against its directory first
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    desugar.include_stack.push(dir);
+    let res = desugar_commands(program, desugar, get_all_proofs, seminaive_transform);
+    desugar.include_stack.pop();
+    res
+}
+
 pub(crate) fn desugar_commands(
     program: Vec<Command>,
     desugar: &mut Desugar,
@@ -676,7 +1002,23 @@ pub(crate) fn desugar_commands(
     seminaive_transform: bool,
 ) -> Result<Vec<NormCommand>, Error> {
     let mut res = vec![];
+    // the shared bindings at each push in this program, restored at the matching pop.
+    // Pushes and pops that come one command at a time are undone by cloning the e-graph instead.
+    let mut memo_scopes = vec![];
     for command in program {
+        match command {
+            Command::Push(n) => {
+                memo_scopes.extend((0..n).map(|_| desugar.session_memo.clone()));
+            }
+            Command::Pop(n) => {
+                for _ in 0..n {
+                    if let Some(session_memo) = memo_scopes.pop() {
+                        desugar.session_memo = session_memo;
+                    }
+                }
+            }
+            _ => {}
+        }
         let desugared = desugar_command(command, desugar, get_all_proofs, seminaive_transform)?;
         res.extend(desugared);
     }
@@ -690,8 +1032,16 @@ impl Clone for Desugar {
             next_command_id: self.next_command_id,
             parser: ast::parse::ProgramParser::new(),
             number_underscores: self.number_underscores,
+            fresh_prefix: self.fresh_prefix.clone(),
             global_variables: self.global_variables.clone(),
+            constructor_fields: self.constructor_fields.clone(),
+            merged_constructors: self.merged_constructors.clone(),
             type_info: self.type_info.clone(),
+            include_paths: self.include_paths.clone(),
+            include_stack: self.include_stack.clone(),
+            included_files: self.included_files.clone(),
+            session_memo: self.session_memo.clone(),
+            rule_names: self.rule_names.clone(),
         }
     }
 }
@@ -704,14 +1054,66 @@ impl Desugar {
         ))
     }
 
+    /// Whether the expression is built from constructors, literals and variables only,
+    /// so evaluating it again always gives the same value.
+    fn is_constructor_term(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Lit(..) | Expr::Var(..) => true,
+            Expr::Call(_, f, args) => {
+                self.constructor_fields.contains_key(f)
+                    && args.iter().all(|arg| self.is_constructor_term(arg))
+            }
+        }
+    }
+
+    /// Picks the name of a rule the user didn't name, adding a counter to `base`
+    /// when a rule in the same ruleset already has that name.
+    fn fresh_rule_name(&mut self, ruleset: Symbol, base: String) -> Symbol {
+        let mut name = Symbol::from(base.as_str());
+        let mut count = 1;
+        while !self.rule_names.insert((ruleset, name)) {
+            name = format!("{base} #{count}").into();
+            count += 1;
+        }
+        name
+    }
+
+    /// A new name, skipping names that are already bound as globals or functions,
+    /// which commands desugared elsewhere (like a prelude) may have used.
     pub fn get_fresh(&mut self) -> Symbol {
-        self.next_fresh += 1;
-        format!(
-            "v{}{}",
-            self.next_fresh - 1,
-            "_".repeat(self.number_underscores)
-        )
-        .into()
+        loop {
+            self.next_fresh += 1;
+            let name = Symbol::from(format!(
+                "{}{}{}",
+                self.fresh_prefix,
+                self.next_fresh - 1,
+                "_".repeat(self.number_underscores)
+            ));
+            if !self.global_variables.contains(&name)
+                && !self.type_info.func_types.contains_key(&name)
+            {
+                return name;
+            }
+        }
+    }
+
+    /// Finds the file an `include` refers to. It is looked up relative to the
+    /// including file, then as given, then in each of the include paths.
+    fn resolve_include(&self, file: &str) -> Result<PathBuf, Error> {
+        let path = Path::new(file);
+        self.include_stack
+            .last()
+            .map(|dir| dir.join(path))
+            .into_iter()
+            .chain(std::iter::once(path.to_path_buf()))
+            .chain(self.include_paths.iter().map(|dir| dir.join(path)))
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(|| {
+                Error::IoError(
+                    path.to_path_buf(),
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "not found in include paths"),
+                )
+            })
     }
 
     pub fn get_new_id(&mut self) -> CommandId {
@@ -740,18 +1142,18 @@ impl Desugar {
             return *existing;
         }
         let res = match expr {
-            Expr::Lit(l) => {
+            Expr::Lit(_, l) => {
                 let assign = self.get_fresh();
                 res.push(NormAction::LetLit(assign, l.clone()));
                 assign
             }
-            Expr::Var(v) => *v,
-            Expr::Call(f, children) => {
+            Expr::Var(_, v) => *v,
+            Expr::Call(_, f, children) => {
                 let assign = self.get_fresh();
                 let mut new_children = vec![];
                 for child in children {
                     match child {
-                        Expr::Var(v) => {
+                        Expr::Var(_, v) => {
                             new_children.push(*v);
                         }
                         _ => {
@@ -776,13 +1178,19 @@ impl Desugar {
     }
 
     pub fn parse_program(&self, input: &str) -> Result<Vec<Command>, Error> {
+        self.parse_file(None, input)
+    }
+
+    /// Like [`Desugar::parse_program`], but the spans of the parsed
+    /// expressions name `file`.
+    fn parse_file(&self, file: Option<Symbol>, input: &str) -> Result<Vec<Command>, Error> {
         Ok(self
             .parser
-            .parse(input)
+            .parse(&SrcFile::new(file, input), input)
             .map_err(|e| e.map_token(|tok| tok.to_string()))?)
     }
 
-    pub fn declare(&mut self, name: Symbol, sort: Symbol) -> Vec<NCommand> {
+    pub fn declare(&mut self, name: Symbol, sort: Symbol, cost: Option<usize>) -> Vec<NCommand> {
         let fresh = self.get_fresh();
         vec![
             NCommand::Function(FunctionDecl {
@@ -794,7 +1202,7 @@ impl Desugar {
                 default: None,
                 merge: None,
                 merge_action: vec![],
-                cost: None,
+                cost,
                 unextractable: false,
             }),
             NCommand::NormAction(NormAction::Let(name, NormExpr::Call(fresh, vec![]))),
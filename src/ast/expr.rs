@@ -2,12 +2,14 @@ use crate::*;
 use ordered_float::OrderedFloat;
 
 use std::fmt::Display;
+use std::num::IntErrorKind;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub enum Literal {
     Int(i64),
     F64(OrderedFloat<f64>),
     String(Symbol),
+    Bool(bool),
     Unit,
 }
 
@@ -34,32 +36,125 @@ macro_rules! impl_from {
 impl_from!(Int(i64));
 impl_from!(F64(OrderedFloat<f64>));
 impl_from!(String(Symbol));
+impl_from!(Bool(bool));
 
 impl Display for Literal {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
             Literal::Int(i) => Display::fmt(i, f),
             Literal::F64(n) => {
-                // need to display with decimal if there is none
-                let str = n.to_string();
-                if let Ok(_num) = str.parse::<i64>() {
-                    write!(f, "{}.0", str)
-                } else {
-                    write!(f, "{}", str)
-                }
+                // Debug prints the shortest form that reads back as the same bits,
+                // always with a decimal point or an exponent so it isn't taken for an i64.
+                // non-finite values print as NaN, inf and -inf, which the parser reads back
+                write!(f, "{:?}", n.into_inner())
             }
             Literal::String(s) => write!(f, "\"{}\"", s),
+            Literal::Bool(b) => Display::fmt(b, f),
             Literal::Unit => write!(f, "()"),
         }
     }
 }
 
+/// Where an expression starts in the source it was parsed from.
+/// Expressions built in code use [`DUMMY_SPAN`].
+///
+/// Spans never affect equality, ordering or hashing, so two expressions
+/// that only differ in where they were written are the same expression.
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub file: Option<Symbol>,
+    /// 1-based, or 0 for [`DUMMY_SPAN`]
+    pub line: usize,
+    /// 1-based, counted in bytes
+    pub col: usize,
+}
+
+pub const DUMMY_SPAN: Span = Span {
+    file: None,
+    line: 0,
+    col: 0,
+};
+
+impl Span {
+    pub fn is_dummy(&self) -> bool {
+        self.line == 0
+    }
+
+    /// ` at <span>` for a real span and nothing for [`DUMMY_SPAN`],
+    /// for appending to error messages.
+    pub(crate) fn at(&self) -> String {
+        if self.is_dummy() {
+            String::new()
+        } else {
+            format!(" at {self}")
+        }
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+impl PartialOrd for Span {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Span {
+    fn cmp(&self, _other: &Self) -> std::cmp::Ordering {
+        std::cmp::Ordering::Equal
+    }
+}
+
+impl std::hash::Hash for Span {
+    fn hash<H: std::hash::Hasher>(&self, _state: &mut H) {}
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.file {
+            Some(file) => write!(f, "{file}:{}:{}", self.line, self.col),
+            None => write!(f, "{}:{}", self.line, self.col),
+        }
+    }
+}
+
+/// Turns the byte offsets the parser reports into [`Span`]s.
+pub struct SrcFile {
+    name: Option<Symbol>,
+    // the offset of the first byte of each line
+    line_starts: Vec<usize>,
+}
+
+impl SrcFile {
+    pub fn new(name: Option<Symbol>, contents: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(contents.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { name, line_starts }
+    }
+
+    pub fn span(&self, offset: usize) -> Span {
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        Span {
+            file: self.name,
+            line,
+            col: offset - self.line_starts[line - 1] + 1,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
 pub enum Expr {
-    Lit(Literal),
-    Var(Symbol),
+    Lit(Span, Literal),
+    Var(Span, Symbol),
     // TODO make this its own type
-    Call(Symbol, Vec<Self>),
+    Call(Span, Symbol, Vec<Self>),
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
@@ -70,9 +165,11 @@ pub enum NormExpr {
 impl NormExpr {
     pub fn to_expr(&self) -> Expr {
         match self {
-            NormExpr::Call(op, args) => {
-                Expr::Call(*op, args.iter().map(|a| Expr::Var(*a)).collect())
-            }
+            NormExpr::Call(op, args) => Expr::Call(
+                DUMMY_SPAN,
+                *op,
+                args.iter().map(|a| Expr::Var(DUMMY_SPAN, *a)).collect(),
+            ),
         }
     }
 
@@ -92,28 +189,34 @@ impl NormExpr {
 
 impl Expr {
     pub fn is_var(&self) -> bool {
-        matches!(self, Expr::Var(_))
+        matches!(self, Expr::Var(..))
     }
 
     pub fn call(op: impl Into<Symbol>, children: impl IntoIterator<Item = Self>) -> Self {
-        Self::Call(op.into(), children.into_iter().collect())
+        Self::Call(DUMMY_SPAN, op.into(), children.into_iter().collect())
     }
 
     pub fn lit(lit: impl Into<Literal>) -> Self {
-        Self::Lit(lit.into())
+        Self::Lit(DUMMY_SPAN, lit.into())
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Lit(span, _) | Expr::Var(span, _) | Expr::Call(span, ..) => *span,
+        }
     }
 
     pub fn get_var(&self) -> Option<Symbol> {
         match self {
-            Expr::Var(v) => Some(*v),
+            Expr::Var(_, v) => Some(*v),
             _ => None,
         }
     }
 
     fn children(&self) -> &[Self] {
         match self {
-            Expr::Var(_) | Expr::Lit(_) => &[],
-            Expr::Call(_, children) => children,
+            Expr::Var(..) | Expr::Lit(..) => &[],
+            Expr::Call(_, _, children) => children,
         }
     }
 
@@ -123,6 +226,12 @@ impl Expr {
         size
     }
 
+    /// The length of the longest path from the root to a leaf, counting both.
+    /// Variables, literals and calls without arguments have depth 1.
+    pub fn depth(&self) -> usize {
+        self.fold(&mut |_e, child_depths| 1 + child_depths.into_iter().max().unwrap_or(0))
+    }
+
     pub fn walk(&self, pre: &mut impl FnMut(&Self), post: &mut impl FnMut(&Self)) {
         pre(self);
         self.children()
@@ -136,22 +245,52 @@ impl Expr {
         f(self, ts)
     }
 
+    /// Like [`Expr::fold`], but stops at the first error.
+    /// Children are folded from left to right.
+    pub fn try_fold<Out, E>(
+        &self,
+        f: &mut impl FnMut(&Self, Vec<Out>) -> Result<Out, E>,
+    ) -> Result<Out, E> {
+        let ts = self
+            .children()
+            .iter()
+            .map(|child| child.try_fold(f))
+            .collect::<Result<_, _>>()?;
+        f(self, ts)
+    }
+
     pub fn map(&self, f: &mut impl FnMut(&Self) -> Self) -> Self {
         match self {
-            Expr::Lit(_) => f(self),
-            Expr::Var(_) => f(self),
-            Expr::Call(op, children) => {
+            Expr::Lit(..) => f(self),
+            Expr::Var(..) => f(self),
+            Expr::Call(span, op, children) => {
                 let children = children.iter().map(|c| c.map(f)).collect();
-                f(&Expr::Call(*op, children))
+                f(&Expr::Call(*span, *op, children))
+            }
+        }
+    }
+
+    /// Like [`Expr::map`], rewriting bottom-up, but stops at the first error.
+    /// Children are rewritten from left to right.
+    pub fn try_map<E>(&self, f: &mut impl FnMut(&Self) -> Result<Self, E>) -> Result<Self, E> {
+        match self {
+            Expr::Lit(..) => f(self),
+            Expr::Var(..) => f(self),
+            Expr::Call(span, op, children) => {
+                let children = children
+                    .iter()
+                    .map(|c| c.try_map(f))
+                    .collect::<Result<_, _>>()?;
+                f(&Expr::Call(*span, *op, children))
             }
         }
     }
 
     pub(crate) fn to_sexp(&self) -> Sexp {
         let res = match self {
-            Expr::Lit(lit) => Sexp::String(lit.to_string()),
-            Expr::Var(v) => Sexp::String(v.to_string()),
-            Expr::Call(op, children) => Sexp::List(
+            Expr::Lit(_, lit) => Sexp::String(lit.to_string()),
+            Expr::Var(_, v) => Sexp::String(v.to_string()),
+            Expr::Call(_, op, children) => Sexp::List(
                 vec![Sexp::String(op.to_string())]
                     .into_iter()
                     .chain(children.iter().map(|c| c.to_sexp()))
@@ -161,25 +300,99 @@ impl Expr {
         res
     }
 
+    /// Replaces the variables in `canon` with their expressions, leaving other variables as they are.
     pub fn subst(&self, canon: &HashMap<Symbol, Expr>) -> Self {
         match self {
-            Expr::Lit(_lit) => self.clone(),
-            Expr::Var(v) => canon.get(v).cloned().unwrap_or_else(|| self.clone()),
-            Expr::Call(op, children) => {
+            Expr::Lit(..) => self.clone(),
+            Expr::Var(_, v) => canon.get(v).cloned().unwrap_or_else(|| self.clone()),
+            Expr::Call(span, op, children) => {
                 let children = children.iter().map(|c| c.subst(canon)).collect();
-                Expr::Call(*op, children)
+                Expr::Call(*span, *op, children)
+            }
+        }
+    }
+
+    /// Renames the function of every call with `head` and every variable with `leaf`.
+    /// Literals are kept as they are.
+    pub fn map_symbols(
+        &self,
+        head: &mut impl FnMut(Symbol) -> Symbol,
+        leaf: &mut impl FnMut(Symbol) -> Symbol,
+    ) -> Self {
+        match self {
+            Expr::Lit(..) => self.clone(),
+            Expr::Var(span, v) => Expr::Var(*span, leaf(*v)),
+            Expr::Call(span, op, children) => {
+                let op = head(*op);
+                let children = children.iter().map(|c| c.map_symbols(head, leaf)).collect();
+                Expr::Call(*span, op, children)
             }
         }
     }
 
     pub fn vars(&self) -> impl Iterator<Item = Symbol> + '_ {
         let iterator: Box<dyn Iterator<Item = Symbol>> = match self {
-            Expr::Lit(_) => Box::new(std::iter::empty()),
-            Expr::Var(v) => Box::new(std::iter::once(*v)),
-            Expr::Call(_, exprs) => Box::new(exprs.iter().flat_map(|e| e.vars())),
+            Expr::Lit(..) => Box::new(std::iter::empty()),
+            Expr::Var(_, v) => Box::new(std::iter::once(*v)),
+            Expr::Call(_, _, exprs) => Box::new(exprs.iter().flat_map(|e| e.vars())),
         };
         iterator
     }
+
+    /// Like [`Expr::vars`], but with the span of each occurrence.
+    pub fn vars_with_spans(&self) -> impl Iterator<Item = (Span, Symbol)> + '_ {
+        let iterator: Box<dyn Iterator<Item = (Span, Symbol)>> = match self {
+            Expr::Lit(..) => Box::new(std::iter::empty()),
+            Expr::Var(span, v) => Box::new(std::iter::once((*span, *v))),
+            Expr::Call(_, _, exprs) => Box::new(exprs.iter().flat_map(|e| e.vars_with_spans())),
+        };
+        iterator
+    }
+
+    /// Whether the two expressions are equal up to a consistent renaming of variables.
+    /// Literals and the names of calls have to match exactly.
+    pub fn alpha_eq(&self, other: &Self) -> bool {
+        fn go(
+            e1: &Expr,
+            e2: &Expr,
+            forward: &mut HashMap<Symbol, Symbol>,
+            backward: &mut HashMap<Symbol, Symbol>,
+        ) -> bool {
+            match (e1, e2) {
+                (Expr::Lit(_, l1), Expr::Lit(_, l2)) => l1 == l2,
+                (Expr::Var(_, v1), Expr::Var(_, v2)) => {
+                    // the renaming has to be a bijection
+                    *forward.entry(*v1).or_insert(*v2) == *v2
+                        && *backward.entry(*v2).or_insert(*v1) == *v1
+                }
+                (Expr::Call(_, f1, args1), Expr::Call(_, f2, args2)) => {
+                    f1 == f2
+                        && args1.len() == args2.len()
+                        && args1
+                            .iter()
+                            .zip(args2)
+                            .all(|(a1, a2)| go(a1, a2, forward, backward))
+                }
+                _ => false,
+            }
+        }
+        go(
+            self,
+            other,
+            &mut HashMap::default(),
+            &mut HashMap::default(),
+        )
+    }
+
+    /// Whether the expression has no variables, so it can be evaluated directly.
+    pub fn is_ground(&self) -> bool {
+        self.vars().next().is_none()
+    }
+
+    /// The variables of the expression that are not in `bound`.
+    pub fn free_vars(&self, bound: &HashSet<Symbol>) -> HashSet<Symbol> {
+        self.vars().filter(|v| !bound.contains(v)).collect()
+    }
 }
 
 impl Display for NormExpr {
@@ -194,12 +407,111 @@ impl Display for Expr {
     }
 }
 
+/// A handle to an expression stored in an [`ExprInterner`]. Two handles from
+/// the same interner are equal exactly when their expressions are.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct ExprId(usize);
+
+/// An expression node whose children are already interned.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum ExprNode {
+    Lit(Literal),
+    Var(Symbol),
+    Call(Symbol, Vec<ExprId>),
+}
+
+/// Stores each distinct expression once, handing out [`ExprId`]s for them.
+/// Expressions are interned bottom-up, so equal subexpressions share an id
+/// and hashing a node only looks at its head and its children's ids.
+/// Only the nodes are stored; [`ExprInterner::to_expr`] builds the tree again.
+#[derive(Debug, Default, Clone)]
+pub struct ExprInterner {
+    nodes: IndexSet<ExprNode>,
+}
+
+impl ExprInterner {
+    pub fn intern(&mut self, expr: Expr) -> ExprId {
+        let node = match expr {
+            Expr::Lit(_, lit) => ExprNode::Lit(lit),
+            Expr::Var(_, var) => ExprNode::Var(var),
+            Expr::Call(_, head, args) => {
+                let args = args.into_iter().map(|arg| self.intern(arg)).collect();
+                return self.intern_call(head, args);
+            }
+        };
+        ExprId(self.nodes.insert_full(node).0)
+    }
+
+    /// Interns a call to `head` on expressions that are already interned,
+    /// so callers don't have to build the tree first.
+    pub fn intern_call(&mut self, head: Symbol, args: Vec<ExprId>) -> ExprId {
+        assert!(
+            args.iter().all(|arg| arg.0 < self.nodes.len()),
+            "ExprId from a different interner"
+        );
+        ExprId(self.nodes.insert_full(ExprNode::Call(head, args)).0)
+    }
+
+    /// Builds the expression behind `id`, which must come from this interner.
+    /// Its spans are [`DUMMY_SPAN`].
+    pub fn to_expr(&self, id: ExprId) -> Expr {
+        match self
+            .nodes
+            .get_index(id.0)
+            .expect("ExprId from a different interner")
+        {
+            ExprNode::Lit(lit) => Expr::Lit(DUMMY_SPAN, lit.clone()),
+            ExprNode::Var(var) => Expr::Var(DUMMY_SPAN, *var),
+            ExprNode::Call(head, args) => Expr::Call(
+                DUMMY_SPAN,
+                *head,
+                args.iter().map(|arg| self.to_expr(*arg)).collect(),
+            ),
+        }
+    }
+
+    /// The number of distinct expressions, counting every subexpression.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+/// Parses an integer literal: an optional sign, an optional `0x`, `0o` or `0b`
+/// radix prefix, then digits that may be separated by underscores.
+pub(crate) fn parse_int_literal(s: &str) -> Result<i64, String> {
+    let (sign, unsigned) = match s.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", s),
+    };
+    let (radix, digits) = if let Some(digits) = unsigned.strip_prefix("0x") {
+        (16, digits)
+    } else if let Some(digits) = unsigned.strip_prefix("0o") {
+        (8, digits)
+    } else if let Some(digits) = unsigned.strip_prefix("0b") {
+        (2, digits)
+    } else {
+        (10, unsigned)
+    };
+    // the sign is parsed together with the digits, so i64::MIN doesn't overflow
+    let digits = format!("{sign}{}", digits.replace('_', ""));
+    i64::from_str_radix(&digits, radix).map_err(|err| match err.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+            format!("Integer literal {s} doesn't fit in an i64")
+        }
+        _ => format!("Invalid integer literal {s}"),
+    })
+}
+
 // currently only used for testing, but no reason it couldn't be used elsewhere later
 #[cfg(test)]
 pub(crate) fn parse_expr(s: &str) -> Result<Expr, lalrpop_util::ParseError<usize, String, String>> {
     let parser = ast::parse::ExprParser::new();
     parser
-        .parse(s)
+        .parse(&SrcFile::new(None, s), s)
         .map_err(|e| e.map_token(|tok| tok.to_string()))
 }
 
@@ -213,4 +525,227 @@ mod tests {
         let e = parse_expr(s).unwrap();
         assert_eq!(format!("{}", e), s);
     }
+
+    #[test]
+    fn test_int_literal_bounds() {
+        for i in [i64::MIN, i64::MAX] {
+            let s = i.to_string();
+            let e = parse_expr(&s).unwrap();
+            assert_eq!(e, Expr::lit(i));
+            assert_eq!(format!("{}", e), s);
+        }
+        assert!(parse_expr("9223372036854775808").is_err());
+        assert!(parse_expr("-9223372036854775809").is_err());
+    }
+
+    #[test]
+    fn test_radix_int_literals() {
+        for (s, i) in [
+            ("0xFF", 255),
+            ("0xdead_beef", 0xdead_beef),
+            ("-0x10", -16),
+            ("0o17", 15),
+            ("-0o7", -7),
+            ("0b1010", 10),
+            ("0b1111_0000", 240),
+            ("0x7FFF_FFFF_FFFF_FFFF", i64::MAX),
+            ("-0x8000_0000_0000_0000", i64::MIN),
+        ] {
+            assert_eq!(parse_expr(s).unwrap(), Expr::lit(i), "{s}");
+        }
+        assert!(parse_expr("0x8000_0000_0000_0000").is_err());
+        assert!(parse_expr("-0x8000_0000_0000_0001").is_err());
+        assert!(parse_expr("0x_").is_err());
+    }
+
+    #[test]
+    fn test_negative_literals() {
+        // a minus sign directly before a digit is part of the literal,
+        // anywhere else it is the name `-`
+        for (s, e) in [
+            ("-3", Expr::lit(-3i64)),
+            ("-2.5", Expr::lit(OrderedFloat(-2.5))),
+            ("(- 3)", Expr::call("-", [Expr::lit(3i64)])),
+            (
+                "(- -3 2)",
+                Expr::call("-", [Expr::lit(-3i64), Expr::lit(2i64)]),
+            ),
+            (
+                "(- a -2.5)",
+                Expr::call(
+                    "-",
+                    [
+                        Expr::Var(DUMMY_SPAN, "a".into()),
+                        Expr::lit(OrderedFloat(-2.5)),
+                    ],
+                ),
+            ),
+        ] {
+            assert_eq!(parse_expr(s).unwrap(), e, "{s}");
+            assert_eq!(format!("{e}"), s);
+        }
+    }
+
+    #[test]
+    fn test_f64_roundtrip() {
+        for f in [1e10, 0.1, 1.5e-8, 1e300, -2.5e-3, 3.0] {
+            let e = Expr::lit(OrderedFloat(f));
+            let s = format!("{}", e);
+            match parse_expr(&s).unwrap() {
+                Expr::Lit(_, Literal::F64(g)) => assert_eq!(g.to_bits(), f.to_bits(), "{s}"),
+                other => panic!("{s} parsed as {other}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_finite_f64_roundtrip() {
+        for f in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let e = Expr::lit(OrderedFloat(f));
+            let s = format!("{}", e);
+            assert_eq!(parse_expr(&s).unwrap(), e, "{s} didn't round-trip");
+        }
+    }
+
+    #[test]
+    fn test_alpha_eq() {
+        let e = parse_expr("(f a b)").unwrap();
+        assert!(e.alpha_eq(&parse_expr("(f x y)").unwrap()));
+        assert!(!e.alpha_eq(&parse_expr("(f x x)").unwrap()));
+        assert!(!parse_expr("(f x x)").unwrap().alpha_eq(&e));
+        assert!(!e.alpha_eq(&parse_expr("(g x y)").unwrap()));
+        assert!(!e.alpha_eq(&parse_expr("(f x 1)").unwrap()));
+    }
+
+    #[test]
+    fn test_try_fold_short_circuits() {
+        let e = parse_expr("(+ 1 (/ (+ 2 3) (- 4 4)) (f))").unwrap();
+        let mut visited_f = false;
+        let res = e.try_fold(&mut |e, args: Vec<i64>| match e {
+            Expr::Lit(_, Literal::Int(i)) => Ok(*i),
+            Expr::Call(_, op, _) => match (op.as_str(), args.as_slice()) {
+                ("+", _) => Ok(args.iter().sum()),
+                ("-", [a, b]) => Ok(a - b),
+                ("/", [_, 0]) => Err("division by zero"),
+                ("/", [a, b]) => Ok(a / b),
+                _ => {
+                    visited_f = true;
+                    Err("unknown function")
+                }
+            },
+            _ => Err("unexpected expression"),
+        });
+        assert_eq!(res, Err("division by zero"));
+        assert!(!visited_f);
+    }
+
+    #[test]
+    fn test_try_map() {
+        let e = parse_expr("(f (g x) (h (g y)))").unwrap();
+        let mut rename = |e: &Expr| match e {
+            Expr::Call(_, op, args) if op.as_str() == "g" => Ok(Expr::call("k", args.clone())),
+            Expr::Call(_, op, _) if op.as_str() == "bad" => Err(e.clone()),
+            _ => Ok(e.clone()),
+        };
+        assert_eq!(
+            e.try_map(&mut rename),
+            Ok(parse_expr("(f (k x) (h (k y)))").unwrap())
+        );
+
+        let e = parse_expr("(f (bad 1) (g (bad 2)))").unwrap();
+        let mut seen = vec![];
+        let res = e.try_map(&mut |e| {
+            seen.push(e.to_string());
+            rename(e)
+        });
+        assert_eq!(res, Err(parse_expr("(bad 1)").unwrap()));
+        assert_eq!(seen, vec!["1", "(bad 1)"]);
+    }
+
+    #[test]
+    fn test_subst() {
+        let e = parse_expr("(f x (g y) z)").unwrap();
+        let canon = HashMap::from_iter([
+            (Symbol::from("x"), parse_expr("(h 1)").unwrap()),
+            (Symbol::from("y"), parse_expr("w").unwrap()),
+        ]);
+        assert_eq!(e.subst(&canon), parse_expr("(f (h 1) (g w) z)").unwrap());
+    }
+
+    #[test]
+    fn test_map_symbols() {
+        let e = parse_expr("(f x (g y 1) x)").unwrap();
+        let renamed = e.map_symbols(&mut |op| format!("{op}-new").into(), &mut |v| {
+            format!("?{v}").into()
+        });
+        assert_eq!(renamed, parse_expr("(f-new ?x (g-new ?y 1) ?x)").unwrap());
+    }
+
+    #[test]
+    fn test_depth() {
+        assert_eq!(parse_expr("x").unwrap().depth(), 1);
+        assert_eq!(parse_expr("(f)").unwrap().depth(), 1);
+        assert_eq!(parse_expr("(f (g (h 1)) 2)").unwrap().depth(), 4);
+    }
+
+    #[test]
+    fn test_ground_and_free_vars() {
+        assert!(parse_expr(r#"(f (g 1) "a")"#).unwrap().is_ground());
+
+        let e = parse_expr("(f x (g y 2) x)").unwrap();
+        assert!(!e.is_ground());
+        let bound = HashSet::from_iter([Symbol::from("y")]);
+        assert_eq!(e.free_vars(&bound), HashSet::from_iter([Symbol::from("x")]));
+    }
+
+    #[test]
+    fn test_vars_with_spans() {
+        let e = parse_expr("(f x\n   (g y x))").unwrap();
+        let vars: Vec<_> = e
+            .vars_with_spans()
+            .map(|(span, v)| (v.as_str(), span.line, span.col))
+            .collect();
+        assert_eq!(vars, vec![("x", 1, 4), ("y", 2, 7), ("x", 2, 9)]);
+        assert!(e.vars().eq(vars.iter().map(|(v, ..)| Symbol::from(*v))));
+    }
+
+    #[test]
+    fn test_interner_shares_equal_exprs() {
+        fn balanced(depth: usize) -> Expr {
+            if depth == 0 {
+                Expr::lit(1i64)
+            } else {
+                Expr::call("Add", [balanced(depth - 1), balanced(depth - 1)])
+            }
+        }
+        let mut interner = ExprInterner::default();
+        let a = interner.intern(balanced(12));
+        let b = interner.intern(balanced(12));
+        assert_eq!(a, b);
+        // one node per level, since the two halves of each level are the same
+        assert_eq!(interner.len(), 13);
+        assert_eq!(interner.to_expr(a), balanced(12));
+
+        // subexpressions were interned on the way up
+        let c = interner.intern(balanced(11));
+        assert_ne!(a, c);
+        assert_eq!(interner.len(), 13);
+        assert_eq!(interner.to_expr(c).depth(), 12);
+    }
+
+    #[test]
+    fn test_intern_call() {
+        let mut interner = ExprInterner::default();
+        let mut id = interner.intern(Expr::lit(1i64));
+        for _ in 0..12 {
+            id = interner.intern_call("Add".into(), vec![id, id]);
+        }
+        assert_eq!(interner.len(), 13);
+        let expr = interner.to_expr(id);
+        assert_eq!(expr.depth(), 13);
+        assert_eq!(expr.ast_size(), (1 << 13) - 1);
+        // interning the built tree finds the same nodes
+        assert_eq!(interner.intern(expr), id);
+        assert_eq!(interner.len(), 13);
+    }
 }
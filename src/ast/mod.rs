@@ -46,6 +46,9 @@ pub type CommandId = usize;
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Metadata {
     pub id: CommandId,
+    /// The name of the rule, rewrite or birewrite this command came from, if any.
+    /// Both directions of a birewrite share its name.
+    pub rule_name: Option<Symbol>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -79,6 +82,10 @@ impl NormCommand {
             NCommand::Check(facts) => {
                 Command::Check(NormRule::resugar_facts(facts, &mut Default::default()))
             }
+            NCommand::CheckIn(config, facts) => Command::CheckIn(
+                config.to_run_config(),
+                NormRule::resugar_facts(facts, &mut Default::default()),
+            ),
             _ => self.command.to_command(),
         }
     }
@@ -99,14 +106,23 @@ pub enum NCommand {
         rule: NormRule,
     },
     NormAction(NormAction),
+    /// A batch of top-level actions, run one after another before a single rebuild.
+    NormActions(Vec<NormAction>),
     RunSchedule(NormSchedule),
     Check(Vec<NormFact>),
+    /// Run and check in a pushed scope, which is popped whether or not the check holds.
+    CheckIn(NormRunConfig, Vec<NormFact>),
     CheckProof,
     PrintTable(Symbol, usize),
-    PrintSize(Symbol),
+    /// Print every function whose output is the given sort.
+    PrintBySort(Symbol, usize),
+    PrintSize(Option<Symbol>),
+    /// Print a hash of the e-graph that doesn't depend on e-class ids.
+    PrintFingerprint,
     Output {
         file: String,
         exprs: Vec<Expr>,
+        mode: OutputMode,
     },
     Push(usize),
     Pop(usize),
@@ -145,15 +161,25 @@ impl NCommand {
             },
             NCommand::RunSchedule(schedule) => Command::RunSchedule(schedule.to_schedule()),
             NCommand::NormAction(action) => Command::Action(action.to_action()),
+            NCommand::NormActions(actions) => {
+                Command::Actions(actions.iter().map(|action| action.to_action()).collect())
+            }
             NCommand::Check(facts) => {
                 Command::Check(facts.iter().map(|fact| fact.to_fact()).collect())
             }
+            NCommand::CheckIn(config, facts) => Command::CheckIn(
+                config.to_run_config(),
+                facts.iter().map(|fact| fact.to_fact()).collect(),
+            ),
             NCommand::CheckProof => Command::CheckProof,
             NCommand::PrintTable(name, n) => Command::PrintTable(*name, *n),
+            NCommand::PrintBySort(sort, n) => Command::PrintBySort(*sort, *n),
             NCommand::PrintSize(name) => Command::PrintSize(*name),
-            NCommand::Output { file, exprs } => Command::Output {
+            NCommand::PrintFingerprint => Command::PrintFingerprint,
+            NCommand::Output { file, exprs, mode } => Command::Output {
                 file: file.to_string(),
                 exprs: exprs.clone(),
+                mode: *mode,
             },
             NCommand::Push(n) => Command::Push(*n),
             NCommand::Pop(n) => Command::Pop(*n),
@@ -165,6 +191,15 @@ impl NCommand {
         }
     }
 
+    /// The top-level actions run by this command, if any.
+    pub(crate) fn top_level_actions(&self) -> &[NormAction] {
+        match self {
+            NCommand::NormAction(action) => std::slice::from_ref(action),
+            NCommand::NormActions(actions) => actions,
+            _ => &[],
+        }
+    }
+
     pub fn map_exprs(&self, f: &mut impl FnMut(&NormExpr) -> NormExpr) -> NCommand {
         match self {
             // Don't map over setoption
@@ -186,15 +221,25 @@ impl NCommand {
                 rule: rule.map_exprs(f),
             },
             NCommand::NormAction(action) => NCommand::NormAction(action.map_exprs(f)),
+            NCommand::NormActions(actions) => {
+                NCommand::NormActions(actions.iter().map(|action| action.map_exprs(f)).collect())
+            }
             NCommand::Check(facts) => {
                 NCommand::Check(facts.iter().map(|fact| fact.map_exprs(f)).collect())
             }
+            NCommand::CheckIn(config, facts) => NCommand::CheckIn(
+                config.clone(),
+                facts.iter().map(|fact| fact.map_exprs(f)).collect(),
+            ),
             NCommand::CheckProof => NCommand::CheckProof,
             NCommand::PrintTable(name, n) => NCommand::PrintTable(*name, *n),
+            NCommand::PrintBySort(sort, n) => NCommand::PrintBySort(*sort, *n),
             NCommand::PrintSize(name) => NCommand::PrintSize(*name),
-            NCommand::Output { file, exprs } => NCommand::Output {
+            NCommand::PrintFingerprint => NCommand::PrintFingerprint,
+            NCommand::Output { file, exprs, mode } => NCommand::Output {
                 file: file.to_string(),
                 exprs: exprs.clone(),
+                mode: *mode,
             },
             NCommand::Push(n) => NCommand::Push(*n),
             NCommand::Pop(n) => NCommand::Pop(*n),
@@ -210,9 +255,13 @@ impl NCommand {
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Schedule {
     Saturate(Box<Schedule>),
+    /// Runs the schedule at most the given number of times, stopping early
+    /// after a pass that changes nothing.
     Repeat(usize, Box<Schedule>),
     Run(RunConfig),
     Sequence(Vec<Schedule>),
+    /// Runs the schedule until it finishes or the time is up, checked between iterations.
+    Timeout(Duration, Box<Schedule>),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -221,6 +270,7 @@ pub enum NormSchedule {
     Repeat(usize, Box<NormSchedule>),
     Run(NormRunConfig),
     Sequence(Vec<NormSchedule>),
+    Timeout(Duration, Box<NormSchedule>),
 }
 
 impl NormSchedule {
@@ -234,6 +284,20 @@ impl NormSchedule {
             NormSchedule::Sequence(scheds) => {
                 Schedule::Sequence(scheds.iter().map(|sched| sched.to_schedule()).collect())
             }
+            NormSchedule::Timeout(duration, sched) => {
+                Schedule::Timeout(*duration, Box::new(sched.to_schedule()))
+            }
+        }
+    }
+
+    /// Whether any run in this schedule stops early on `:until` facts.
+    pub fn has_until(&self) -> bool {
+        match self {
+            NormSchedule::Run(config) => config.until.is_some(),
+            NormSchedule::Saturate(sched)
+            | NormSchedule::Repeat(_, sched)
+            | NormSchedule::Timeout(_, sched) => sched.has_until(),
+            NormSchedule::Sequence(scheds) => scheds.iter().any(|sched| sched.has_until()),
         }
     }
 
@@ -246,6 +310,9 @@ impl NormSchedule {
             NormSchedule::Repeat(size, sched) => {
                 Schedule::Repeat(*size, Box::new(sched.map_run_commands(f)))
             }
+            NormSchedule::Timeout(duration, sched) => {
+                Schedule::Timeout(*duration, Box::new(sched.map_run_commands(f)))
+            }
             NormSchedule::Sequence(scheds) => Schedule::Sequence(
                 scheds
                     .iter()
@@ -303,6 +370,9 @@ impl ToSexp for Schedule {
             Schedule::Repeat(size, sched) => list!("repeat", size, sched),
             Schedule::Run(config) => config.to_sexp(),
             Schedule::Sequence(scheds) => list!("seq", ++ scheds),
+            Schedule::Timeout(duration, sched) => {
+                list!("timeout", duration.as_millis() as usize, sched)
+            }
         }
     }
 }
@@ -313,6 +383,19 @@ impl Display for Schedule {
     }
 }
 
+impl Schedule {
+    /// The `:until` facts' expressions, in order.
+    pub(crate) fn exprs(&self) -> Vec<&Expr> {
+        match self {
+            Schedule::Run(config) => config.exprs(),
+            Schedule::Saturate(sched)
+            | Schedule::Repeat(_, sched)
+            | Schedule::Timeout(_, sched) => sched.exprs(),
+            Schedule::Sequence(scheds) => scheds.iter().flat_map(|sched| sched.exprs()).collect(),
+        }
+    }
+}
+
 impl Display for NormSchedule {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.to_schedule())
@@ -329,10 +412,18 @@ pub enum Command {
     Datatype {
         name: Symbol,
         variants: Vec<Variant>,
+        /// Also declare a relation `Ctor?` for each variant `Ctor`,
+        /// which holds for every value built by that constructor.
+        discriminators: bool,
+        /// The merge shared by every constructor, used instead of unioning
+        /// when the same call ends up with two different e-classes.
+        merge: Option<Expr>,
     },
     Declare {
         name: Symbol,
         sort: Symbol,
+        /// The extraction cost of the constant backing the global.
+        cost: Option<usize>,
     },
     Sort(Symbol, Option<(Symbol, Vec<Expr>)>),
     Function(FunctionDecl),
@@ -343,47 +434,96 @@ pub enum Command {
         rule: Rule,
     },
     Rewrite(Symbol, Rewrite),
-    BiRewrite(Symbol, Rewrite),
+    BiRewrite(Symbol, Rewrite, DirectedConditions),
     Action(Action),
+    /// A batch of actions, desugared together so that
+    /// repeated subexpressions are only added once.
+    Actions(Vec<Action>),
     RunSchedule(Schedule),
+    /// Run the schedule in a fresh scope and extract the expression.
+    /// With a non-zero number of variants, extracts that many
+    /// distinct forms instead of only the best one.
     Simplify {
         expr: Expr,
         schedule: Schedule,
+        variants: usize,
     },
-    Calc(Vec<IdentSort>, Vec<Expr>),
+    /// Proves each expression equal to the next by running the given ruleset.
+    Calc(Vec<IdentSort>, Vec<Expr>, Symbol),
     Extract {
         variants: usize,
         fact: Fact,
     },
     // TODO: this could just become an empty query
     Check(Vec<Fact>),
+    /// Run the config in a fresh scope, check the facts, and pop,
+    /// leaving the e-graph unchanged.
+    CheckIn(RunConfig, Vec<Fact>),
     CheckProof,
     PrintTable(Symbol, usize),
-    PrintSize(Symbol),
+    /// Print every function whose output is the given sort.
+    PrintBySort(Symbol, usize),
+    PrintSize(Option<Symbol>),
+    /// Print a hash of the e-graph that doesn't depend on e-class ids.
+    PrintFingerprint,
     Input {
         name: Symbol,
         file: String,
     },
+    /// Extract each expression and write it on its own line, to `file` or,
+    /// with [`OutputMode::Stdout`], to the printed messages.
     Output {
         file: String,
         exprs: Vec<Expr>,
+        mode: OutputMode,
     },
     Push(usize),
     Pop(usize),
     Fail(Box<Command>),
     // TODO desugar include
+    /// Includes a file, unless the same file was already included.
     Include(String),
+    /// Includes a file even if it was already included.
+    IncludeAlways(String),
 }
 
 impl ToSexp for Command {
     fn to_sexp(&self) -> Sexp {
         match self {
             Command::SetOption { name, value } => list!("set-option", name, value),
-            Command::Rewrite(name, rewrite) => rewrite.to_sexp(*name, false),
-            Command::BiRewrite(name, rewrite) => rewrite.to_sexp(*name, true),
-            Command::Datatype { name, variants } => list!("datatype", name, ++ variants),
-            Command::Declare { name, sort } => list!("declare", name, sort),
+            Command::Rewrite(name, rewrite) => rewrite.to_sexp(*name, None),
+            Command::BiRewrite(name, rewrite, directed) => rewrite.to_sexp(*name, Some(directed)),
+            Command::Datatype {
+                name,
+                variants,
+                discriminators,
+                merge,
+            } => {
+                let mut res = vec![Sexp::String("datatype".into()), name.to_sexp()];
+                res.extend(variants.iter().map(|variant| variant.to_sexp()));
+                if *discriminators {
+                    res.push(Sexp::String(":discriminators".into()));
+                }
+                if let Some(merge) = merge {
+                    res.push(Sexp::String(":merge".into()));
+                    res.push(merge.to_sexp());
+                }
+                Sexp::List(res)
+            }
+            Command::Declare { name, sort, cost } => {
+                let mut res = vec![
+                    Sexp::String("declare".into()),
+                    name.to_sexp(),
+                    sort.to_sexp(),
+                ];
+                if let Some(cost) = cost {
+                    res.push(Sexp::String(":cost".into()));
+                    res.push(Sexp::String(cost.to_string()));
+                }
+                Sexp::List(res)
+            }
             Command::Action(a) => a.to_sexp(),
+            Command::Actions(actions) => list!("actions", ++ actions),
             Command::Sort(name, None) => list!("sort", name),
             Command::Sort(name, Some((name2, args))) => list!("sort", name, list!( name2, ++ args)),
             Command::Function(f) => f.to_sexp(),
@@ -394,21 +534,48 @@ impl ToSexp for Command {
                 rule,
             } => rule.to_sexp(*ruleset, *name),
             Command::RunSchedule(sched) => list!("run-schedule", sched),
-            Command::Calc(args, exprs) => list!("calc", list!(++ args), ++ exprs),
+            Command::Calc(args, exprs, ruleset) => {
+                let mut res = vec![Sexp::String("calc".into()), list!(++ args)];
+                res.extend(exprs.iter().map(|expr| expr.to_sexp()));
+                if *ruleset != "".into() {
+                    res.push(Sexp::String(":ruleset".into()));
+                    res.push(ruleset.to_sexp());
+                }
+                Sexp::List(res)
+            }
             Command::Extract { variants, fact } => {
                 list!("query-extract", ":variants", variants, fact)
             }
             Command::Check(facts) => list!("check", ++ facts),
+            Command::CheckIn(config, facts) => list!("check-in", config, ++ facts),
             Command::CheckProof => list!("check-proof"),
             Command::Push(n) => list!("push", n),
             Command::Pop(n) => list!("pop", n),
             Command::PrintTable(name, n) => list!("print-table", name, n),
-            Command::PrintSize(name) => list!("print-size", name),
+            Command::PrintBySort(sort, n) => list!("print-sort", sort, n),
+            Command::PrintSize(name) => list!("print-size", ++ name),
+            Command::PrintFingerprint => list!("print-fingerprint"),
             Command::Input { name, file } => list!("input", name, format!("\"{}\"", file)),
-            Command::Output { file, exprs } => list!("output", format!("\"{}\"", file), ++ exprs),
+            Command::Output { file, exprs, mode } => match mode {
+                OutputMode::Append => list!("output", format!("\"{}\"", file), ++ exprs),
+                OutputMode::Truncate => {
+                    list!("output", ":truncate", format!("\"{}\"", file), ++ exprs)
+                }
+                OutputMode::Stdout => list!("output", ":stdout", ++ exprs),
+            },
             Command::Fail(cmd) => list!("fail", cmd),
             Command::Include(file) => list!("include", format!("\"{}\"", file)),
-            Command::Simplify { expr, schedule } => list!("simplify", schedule, expr),
+            Command::IncludeAlways(file) => list!("include-always", format!("\"{}\"", file)),
+            Command::Simplify {
+                expr,
+                schedule,
+                variants: 0,
+            } => list!("simplify", schedule, expr),
+            Command::Simplify {
+                expr,
+                schedule,
+                variants,
+            } => list!("simplify", ":variants", variants, schedule, expr),
         }
     }
 }
@@ -425,6 +592,76 @@ impl Display for NCommand {
     }
 }
 
+impl Command {
+    /// The expressions written in the command, in the order they appear.
+    /// Their subexpressions aren't listed separately.
+    pub(crate) fn exprs(&self) -> Vec<&Expr> {
+        fn facts(facts: &[Fact]) -> Vec<&Expr> {
+            facts.iter().flat_map(|fact| fact.exprs()).collect()
+        }
+        fn actions(actions: &[Action]) -> Vec<&Expr> {
+            actions.iter().flat_map(|action| action.exprs()).collect()
+        }
+        match self {
+            Command::SetOption { value, .. } => vec![value],
+            Command::Datatype { merge, .. } => merge.iter().collect(),
+            Command::Sort(_, Some((_, args))) => args.iter().collect(),
+            Command::Function(decl) => [&decl.default, &decl.merge]
+                .into_iter()
+                .flatten()
+                .chain(actions(&decl.merge_action))
+                .collect(),
+            Command::Rule { rule, .. } => {
+                let mut exprs = facts(&rule.body);
+                exprs.extend(actions(&rule.head));
+                exprs
+            }
+            Command::Rewrite(_, rewrite) => {
+                let mut exprs = vec![&rewrite.lhs, &rewrite.rhs];
+                exprs.extend(facts(&rewrite.conditions));
+                exprs
+            }
+            Command::BiRewrite(_, rewrite, directed) => {
+                let mut exprs = vec![&rewrite.lhs, &rewrite.rhs];
+                exprs.extend(facts(&rewrite.conditions));
+                exprs.extend(facts(&directed.forward));
+                exprs.extend(facts(&directed.backward));
+                exprs
+            }
+            Command::Action(action) => action.exprs(),
+            Command::Actions(acts) => actions(acts),
+            Command::RunSchedule(sched) => sched.exprs(),
+            Command::Simplify { expr, schedule, .. } => {
+                let mut exprs = schedule.exprs();
+                exprs.push(expr);
+                exprs
+            }
+            Command::Calc(_, exprs, _) | Command::Output { exprs, .. } => exprs.iter().collect(),
+            Command::Extract { fact, .. } => fact.exprs(),
+            Command::Check(fs) => facts(fs),
+            Command::CheckIn(config, fs) => {
+                let mut exprs = config.exprs();
+                exprs.extend(facts(fs));
+                exprs
+            }
+            Command::Fail(command) => command.exprs(),
+            Command::Sort(_, None)
+            | Command::Declare { .. }
+            | Command::AddRuleset(_)
+            | Command::CheckProof
+            | Command::PrintTable(..)
+            | Command::PrintBySort(..)
+            | Command::PrintSize(_)
+            | Command::PrintFingerprint
+            | Command::Input { .. }
+            | Command::Push(_)
+            | Command::Pop(_)
+            | Command::Include(_)
+            | Command::IncludeAlways(_) => vec![],
+        }
+    }
+}
+
 impl Display for Command {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -463,6 +700,21 @@ impl Display for IdentSort {
 pub struct RunConfig {
     pub ruleset: Symbol,
     pub until: Option<Vec<Fact>>,
+    /// Whether the run stops once all of the `until` facts hold or once any of them does.
+    pub until_mode: UntilMode,
+    /// Only search for matches and report how many each rule found,
+    /// without running any actions.
+    pub dry_run: bool,
+}
+
+impl RunConfig {
+    pub(crate) fn exprs(&self) -> Vec<&Expr> {
+        self.until
+            .iter()
+            .flatten()
+            .flat_map(|fact| fact.exprs())
+            .collect()
+    }
 }
 
 impl ToSexp for RunConfig {
@@ -471,8 +723,17 @@ impl ToSexp for RunConfig {
         if self.ruleset != "".into() {
             res.push(Sexp::String(self.ruleset.to_string()));
         }
+        if self.dry_run {
+            res.push(Sexp::String(":dry-run".into()));
+        }
         if let Some(until) = &self.until {
-            res.push(Sexp::String(":until".into()));
+            res.push(Sexp::String(
+                match self.until_mode {
+                    UntilMode::All => ":until",
+                    UntilMode::Any => ":until-any",
+                }
+                .into(),
+            ));
             res.extend(until.iter().map(|fact| fact.to_sexp()));
         }
 
@@ -480,24 +741,90 @@ impl ToSexp for RunConfig {
     }
 }
 
+/// Where an `output` command writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OutputMode {
+    /// Add lines to the end of the file, written `:append` or nothing at all.
+    #[default]
+    Append,
+    /// Replace what the file held before, written `:truncate`.
+    Truncate,
+    /// Print the lines instead of writing a file, written `:stdout`.
+    Stdout,
+}
+
+/// How the `until` facts of a run combine.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UntilMode {
+    /// Stop once all the facts hold together, written `:until`.
+    #[default]
+    All,
+    /// Stop once any one of the facts holds, written `:until-any`.
+    Any,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct NormRunConfig {
     pub ruleset: Symbol,
-    pub until: Option<Vec<NormFact>>,
+    /// The `until` facts in normal form. In [`UntilMode::All`] they are
+    /// flattened together into a single group, in [`UntilMode::Any`]
+    /// each fact is flattened into a group of its own.
+    pub until: Option<Vec<Vec<NormFact>>>,
+    pub until_mode: UntilMode,
+    pub dry_run: bool,
 }
 
 impl NormRunConfig {
     pub fn to_run_config(&self) -> RunConfig {
         RunConfig {
             ruleset: self.ruleset,
-            until: self
-                .until
-                .as_ref()
-                .map(|v| v.iter().map(|f| f.to_fact()).collect()),
+            until: self.until.as_ref().map(|groups| match self.until_mode {
+                UntilMode::All => groups.iter().flatten().map(|f| f.to_fact()).collect(),
+                UntilMode::Any => groups.iter().flat_map(|group| nest_facts(group)).collect(),
+            }),
+            until_mode: self.until_mode,
+            dry_run: self.dry_run,
         }
     }
 }
 
+/// Nests the flattened form of a single fact back up, by inlining each
+/// variable definition into the facts that use it.
+fn nest_facts(facts: &[NormFact]) -> Vec<Fact> {
+    let mut facts = NormRule::resugar_facts(&facts.to_vec(), &mut Default::default());
+    let uses = |fact: &Fact, var: Symbol| {
+        let mut used = false;
+        fact.map_exprs(&mut |expr| {
+            used |= expr.vars().any(|v| v == var);
+            expr.clone()
+        });
+        used
+    };
+    let definition = |facts: &[Fact]| {
+        facts.iter().enumerate().find_map(|(i, fact)| match fact {
+            Fact::Eq(exprs) => match exprs.as_slice() {
+                [Expr::Var(_, var), expr]
+                    if !expr.vars().any(|v| v == *var)
+                        && facts
+                            .iter()
+                            .enumerate()
+                            .any(|(j, other)| j != i && uses(other, *var)) =>
+                {
+                    Some((i, *var, expr.clone()))
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+    };
+    while let Some((i, var, expr)) = definition(&facts) {
+        facts.remove(i);
+        let subst = HashMap::from_iter([(var, expr)]);
+        facts = facts.iter().map(|fact| fact.subst(&subst)).collect();
+    }
+    facts
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FunctionDecl {
     pub name: Symbol,
@@ -514,15 +841,23 @@ pub struct FunctionDecl {
 pub struct Variant {
     pub name: Symbol,
     pub types: Vec<Symbol>,
+    /// Optional field names, one for each of `types`.
+    pub fields: Vec<Option<Symbol>>,
     pub cost: Option<usize>,
 }
 
 impl ToSexp for Variant {
     fn to_sexp(&self) -> Sexp {
         let mut res = vec![Sexp::String(self.name.to_string())];
-        if !self.types.is_empty() {
-            res.extend(self.types.iter().map(|s| Sexp::String(s.to_string())));
-        }
+        res.extend(
+            self.types
+                .iter()
+                .zip(&self.fields)
+                .map(|(ty, field)| match field {
+                    Some(field) => list!(field, ty),
+                    None => ty.to_sexp(),
+                }),
+        );
         if let Some(cost) = self.cost {
             res.push(Sexp::String(":cost".into()));
             res.push(Sexp::String(cost.to_string()));
@@ -566,6 +901,36 @@ impl FunctionDecl {
     }
 }
 
+/// A `:merge` expression keeping the smaller of the `old` and `new` outputs,
+/// for functions whose output is the primitive `sort`.
+pub fn min_merge(sort: Symbol) -> Expr {
+    merge_old_new(if sort == "String".into() {
+        "min-string"
+    } else {
+        "min"
+    })
+}
+
+/// Like [`min_merge`], keeping the larger output.
+pub fn max_merge(sort: Symbol) -> Expr {
+    merge_old_new(if sort == "String".into() {
+        "max-string"
+    } else {
+        "max"
+    })
+}
+
+// the merge variables are bound by the typechecker
+fn merge_old_new(op: &str) -> Expr {
+    Expr::call(
+        op,
+        [
+            Expr::Var(DUMMY_SPAN, "old".into()),
+            Expr::Var(DUMMY_SPAN, "new".into()),
+        ],
+    )
+}
+
 impl ToSexp for FunctionDecl {
     fn to_sexp(&self) -> Sexp {
         let mut res = vec![
@@ -616,6 +981,9 @@ pub enum Fact {
     /// Must be at least two things in an eq fact
     Eq(Vec<Expr>),
     Fact(Expr),
+    /// Matches when the call, whose arguments must be bound
+    /// by the rest of the query, has no row in its table.
+    Absent(Expr),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -625,19 +993,28 @@ pub enum NormFact {
     Compute(Symbol, NormExpr), // compute a primative
     AssignLit(Symbol, Literal),
     ConstrainEq(Symbol, Symbol),
+    Absent(NormExpr),
 }
 
 impl NormFact {
     pub fn to_fact(&self) -> Fact {
         match self {
             NormFact::Assign(symbol, expr) | NormFact::Compute(symbol, expr) => {
-                Fact::Eq(vec![Expr::Var(*symbol), expr.to_expr()])
-            }
-            NormFact::AssignVar(lhs, rhs) => Fact::Eq(vec![Expr::Var(*lhs), Expr::Var(*rhs)]),
-            NormFact::ConstrainEq(lhs, rhs) => Fact::Eq(vec![Expr::Var(*lhs), Expr::Var(*rhs)]),
-            NormFact::AssignLit(symbol, lit) => {
-                Fact::Eq(vec![Expr::Var(*symbol), Expr::Lit(lit.clone())])
+                Fact::Eq(vec![Expr::Var(DUMMY_SPAN, *symbol), expr.to_expr()])
             }
+            NormFact::AssignVar(lhs, rhs) => Fact::Eq(vec![
+                Expr::Var(DUMMY_SPAN, *lhs),
+                Expr::Var(DUMMY_SPAN, *rhs),
+            ]),
+            NormFact::ConstrainEq(lhs, rhs) => Fact::Eq(vec![
+                Expr::Var(DUMMY_SPAN, *lhs),
+                Expr::Var(DUMMY_SPAN, *rhs),
+            ]),
+            NormFact::AssignLit(symbol, lit) => Fact::Eq(vec![
+                Expr::Var(DUMMY_SPAN, *symbol),
+                Expr::Lit(DUMMY_SPAN, lit.clone()),
+            ]),
+            NormFact::Absent(expr) => Fact::Absent(expr.to_expr()),
         }
     }
 
@@ -649,6 +1026,7 @@ impl NormFact {
             NormFact::AssignVar(lhs, rhs) => NormFact::AssignVar(*lhs, *rhs),
             NormFact::ConstrainEq(lhs, rhs) => NormFact::ConstrainEq(*lhs, *rhs),
             NormFact::AssignLit(symbol, lit) => NormFact::AssignLit(*symbol, lit.clone()),
+            NormFact::Absent(expr) => NormFact::Absent(f(expr)),
         }
     }
 
@@ -669,6 +1047,7 @@ impl NormFact {
             NormFact::ConstrainEq(lhs, rhs) => {
                 NormFact::ConstrainEq(fvar(*lhs, false), fvar(*rhs, false))
             }
+            NormFact::Absent(expr) => NormFact::Absent(expr.map_def_use(fvar, false)),
         }
     }
 }
@@ -678,6 +1057,7 @@ impl ToSexp for Fact {
         match self {
             Fact::Eq(exprs) => list!("=", ++ exprs),
             Fact::Fact(expr) => expr.to_sexp(),
+            Fact::Absent(expr) => list!("absent", expr),
         }
     }
 }
@@ -687,6 +1067,14 @@ impl Fact {
         match self {
             Fact::Eq(exprs) => Fact::Eq(exprs.iter().map(f).collect()),
             Fact::Fact(expr) => Fact::Fact(f(expr)),
+            Fact::Absent(expr) => Fact::Absent(f(expr)),
+        }
+    }
+
+    pub(crate) fn exprs(&self) -> Vec<&Expr> {
+        match self {
+            Fact::Eq(exprs) => exprs.iter().collect(),
+            Fact::Fact(expr) | Fact::Absent(expr) => vec![expr],
         }
     }
 
@@ -712,7 +1100,11 @@ pub enum Action {
     Let(Symbol, Expr),
     Set(Symbol, Vec<Expr>, Expr),
     Delete(Symbol, Vec<Expr>),
+    /// Keep the row, but stop it from being matched by rules or extracted.
+    Subsume(Symbol, Vec<Expr>),
     Union(Expr, Expr),
+    /// Make the value the preferred representative of its e-class.
+    Pin(Expr),
     Extract(Expr, Expr),
     Panic(String),
     Expr(Expr),
@@ -727,7 +1119,9 @@ pub enum NormAction {
     Extract(Symbol, Symbol),
     Set(NormExpr, Symbol),
     Delete(NormExpr),
+    Subsume(NormExpr),
     Union(Symbol, Symbol),
+    Pin(Symbol),
     Panic(String),
 }
 
@@ -735,20 +1129,33 @@ impl NormAction {
     pub fn to_action(&self) -> Action {
         match self {
             NormAction::Let(symbol, expr) => Action::Let(*symbol, expr.to_expr()),
-            NormAction::LetVar(symbol, other) => Action::Let(*symbol, Expr::Var(*other)),
-            NormAction::LetLit(symbol, lit) => Action::Let(*symbol, Expr::Lit(lit.clone())),
+            NormAction::LetVar(symbol, other) => {
+                Action::Let(*symbol, Expr::Var(DUMMY_SPAN, *other))
+            }
+            NormAction::LetLit(symbol, lit) => {
+                Action::Let(*symbol, Expr::Lit(DUMMY_SPAN, lit.clone()))
+            }
             NormAction::Set(NormExpr::Call(head, body), other) => Action::Set(
                 *head,
-                body.iter().map(|s| Expr::Var(*s)).collect(),
-                Expr::Var(*other),
+                body.iter().map(|s| Expr::Var(DUMMY_SPAN, *s)).collect(),
+                Expr::Var(DUMMY_SPAN, *other),
             ),
-            NormAction::Extract(symbol, variants) => {
-                Action::Extract(Expr::Var(*symbol), Expr::Var(*variants))
-            }
-            NormAction::Delete(NormExpr::Call(symbol, args)) => {
-                Action::Delete(*symbol, args.iter().map(|s| Expr::Var(*s)).collect())
+            NormAction::Extract(symbol, variants) => Action::Extract(
+                Expr::Var(DUMMY_SPAN, *symbol),
+                Expr::Var(DUMMY_SPAN, *variants),
+            ),
+            NormAction::Delete(NormExpr::Call(symbol, args)) => Action::Delete(
+                *symbol,
+                args.iter().map(|s| Expr::Var(DUMMY_SPAN, *s)).collect(),
+            ),
+            NormAction::Subsume(NormExpr::Call(symbol, args)) => Action::Subsume(
+                *symbol,
+                args.iter().map(|s| Expr::Var(DUMMY_SPAN, *s)).collect(),
+            ),
+            NormAction::Union(lhs, rhs) => {
+                Action::Union(Expr::Var(DUMMY_SPAN, *lhs), Expr::Var(DUMMY_SPAN, *rhs))
             }
-            NormAction::Union(lhs, rhs) => Action::Union(Expr::Var(*lhs), Expr::Var(*rhs)),
+            NormAction::Pin(var) => Action::Pin(Expr::Var(DUMMY_SPAN, *var)),
             NormAction::Panic(msg) => Action::Panic(msg.clone()),
         }
     }
@@ -761,7 +1168,9 @@ impl NormAction {
             NormAction::Set(expr, other) => NormAction::Set(f(expr), *other),
             NormAction::Extract(var, variants) => NormAction::Extract(*var, *variants),
             NormAction::Delete(expr) => NormAction::Delete(f(expr)),
+            NormAction::Subsume(expr) => NormAction::Subsume(f(expr)),
             NormAction::Union(lhs, rhs) => NormAction::Union(*lhs, *rhs),
+            NormAction::Pin(var) => NormAction::Pin(*var),
             NormAction::Panic(msg) => NormAction::Panic(msg.clone()),
         }
     }
@@ -783,7 +1192,9 @@ impl NormAction {
                 NormAction::Extract(fvar(*var, false), fvar(*variants, false))
             }
             NormAction::Delete(expr) => NormAction::Delete(expr.map_def_use(fvar, false)),
+            NormAction::Subsume(expr) => NormAction::Subsume(expr.map_def_use(fvar, false)),
             NormAction::Union(lhs, rhs) => NormAction::Union(fvar(*lhs, false), fvar(*rhs, false)),
+            NormAction::Pin(var) => NormAction::Pin(fvar(*var, false)),
             NormAction::Panic(msg) => NormAction::Panic(msg.clone()),
         }
     }
@@ -795,7 +1206,9 @@ impl ToSexp for Action {
             Action::Let(lhs, rhs) => list!("let", lhs, rhs),
             Action::Set(lhs, args, rhs) => list!("set", list!(lhs, ++ args), rhs),
             Action::Union(lhs, rhs) => list!("union", lhs, rhs),
+            Action::Pin(expr) => list!("pin", expr),
             Action::Delete(lhs, args) => list!("delete", list!(lhs, ++ args)),
+            Action::Subsume(lhs, args) => list!("subsume", list!(lhs, ++ args)),
             Action::Extract(expr, variants) => list!("extract", expr, variants),
             Action::Panic(msg) => list!("panic", format!("\"{}\"", msg.clone())),
             Action::Expr(e) => e.to_sexp(),
@@ -812,13 +1225,26 @@ impl Action {
                 Action::Set(*lhs, args.iter().map(f).collect(), right)
             }
             Action::Delete(lhs, args) => Action::Delete(*lhs, args.iter().map(f).collect()),
+            Action::Subsume(lhs, args) => Action::Subsume(*lhs, args.iter().map(f).collect()),
             Action::Union(lhs, rhs) => Action::Union(f(lhs), f(rhs)),
+            Action::Pin(expr) => Action::Pin(f(expr)),
             Action::Extract(expr, variants) => Action::Extract(f(expr), f(variants)),
             Action::Panic(msg) => Action::Panic(msg.clone()),
             Action::Expr(e) => Action::Expr(f(e)),
         }
     }
 
+    pub(crate) fn exprs(&self) -> Vec<&Expr> {
+        match self {
+            Action::Let(_, expr) | Action::Pin(expr) | Action::Expr(expr) => vec![expr],
+            Action::Set(_, args, rhs) => args.iter().chain([rhs]).collect(),
+            Action::Delete(_, args) | Action::Subsume(_, args) => args.iter().collect(),
+            Action::Union(lhs, rhs) => vec![lhs, rhs],
+            Action::Extract(expr, variants) => vec![expr, variants],
+            Action::Panic(_) => vec![],
+        }
+    }
+
     pub fn replace_canon(&self, canon: &HashMap<Symbol, Expr>) -> Self {
         match self {
             Action::Let(lhs, rhs) => Action::Let(*lhs, rhs.subst(canon)),
@@ -830,7 +1256,11 @@ impl Action {
             Action::Delete(lhs, args) => {
                 Action::Delete(*lhs, args.iter().map(|e| e.subst(canon)).collect())
             }
+            Action::Subsume(lhs, args) => {
+                Action::Subsume(*lhs, args.iter().map(|e| e.subst(canon)).collect())
+            }
             Action::Union(lhs, rhs) => Action::Union(lhs.subst(canon), rhs.subst(canon)),
+            Action::Pin(expr) => Action::Pin(expr.subst(canon)),
             Action::Extract(expr, variants) => {
                 Action::Extract(expr.subst(canon), variants.subst(canon))
             }
@@ -928,7 +1358,7 @@ impl NormRule {
         for (var, id) in &var_to_id {
             let leader = id_to_var.get(&unionfind.find(*id)).unwrap();
             if leader != var {
-                subst.insert(*var, Expr::Var(*leader));
+                subst.insert(*var, Expr::Var(DUMMY_SPAN, *leader));
             }
         }
 
@@ -946,7 +1376,10 @@ impl NormRule {
             if let Some(id) = var_to_id.get(&var) {
                 let leader = id_to_var.get(&unionfind.find(*id)).unwrap();
                 if leader != &var {
-                    res.push(Fact::Eq(vec![Expr::Var(var), Expr::Var(*leader)]));
+                    res.push(Fact::Eq(vec![
+                        Expr::Var(DUMMY_SPAN, var),
+                        Expr::Var(DUMMY_SPAN, *leader),
+                    ]));
                 }
             }
         }
@@ -962,7 +1395,7 @@ impl NormRule {
                 NormAction::Let(symbol, expr) => {
                     let new_expr = expr.to_expr();
                     new_expr.map(&mut |subexpr| {
-                        if let Expr::Var(v) = subexpr {
+                        if let Expr::Var(_, v) = subexpr {
                             used.insert(*v);
                         }
                         subexpr.clone()
@@ -977,33 +1410,45 @@ impl NormRule {
                     }
                 }
                 NormAction::LetVar(symbol, other) => {
-                    let new_expr = subst.get(other).unwrap_or(&Expr::Var(*other)).clone();
+                    let new_expr = subst
+                        .get(other)
+                        .unwrap_or(&Expr::Var(DUMMY_SPAN, *other))
+                        .clone();
                     used.insert(*other);
                     subst.insert(*symbol, new_expr);
                 }
                 NormAction::Extract(symbol, variants) => {
-                    let new_expr = subst.get(symbol).cloned().unwrap_or(Expr::Var(*symbol));
+                    let new_expr = subst
+                        .get(symbol)
+                        .cloned()
+                        .unwrap_or(Expr::Var(DUMMY_SPAN, *symbol));
                     used.insert(*symbol);
-                    let new_expr2 = subst.get(variants).cloned().unwrap_or(Expr::Var(*variants));
+                    let new_expr2 = subst
+                        .get(variants)
+                        .cloned()
+                        .unwrap_or(Expr::Var(DUMMY_SPAN, *variants));
                     used.insert(*variants);
                     head.push(Action::Extract(new_expr, new_expr2));
                 }
                 NormAction::LetLit(symbol, lit) => {
-                    subst.insert(*symbol, Expr::Lit(lit.clone()));
+                    subst.insert(*symbol, Expr::Lit(DUMMY_SPAN, lit.clone()));
                 }
                 NormAction::Set(expr, other) => {
                     let new_expr = expr.to_expr();
                     new_expr.map(&mut |subexpr| {
-                        if let Expr::Var(v) = subexpr {
+                        if let Expr::Var(_, v) = subexpr {
                             used.insert(*v);
                         }
                         subexpr.clone()
                     });
-                    let other_expr = subst.get(other).unwrap_or(&Expr::Var(*other)).clone();
+                    let other_expr = subst
+                        .get(other)
+                        .unwrap_or(&Expr::Var(DUMMY_SPAN, *other))
+                        .clone();
                     used.insert(*other);
                     let substituted = new_expr.subst(subst);
                     match substituted {
-                        Expr::Call(op, children) => {
+                        Expr::Call(_, op, children) => {
                             head.push(Action::Set(op, children, other_expr));
                         }
                         _ => panic!("Expected call in set"),
@@ -1012,25 +1457,54 @@ impl NormRule {
                 NormAction::Delete(expr) => {
                     let new_expr = expr.to_expr();
                     new_expr.map(&mut |subexpr| {
-                        if let Expr::Var(v) = subexpr {
+                        if let Expr::Var(_, v) = subexpr {
                             used.insert(*v);
                         }
                         subexpr.clone()
                     });
                     match new_expr.subst(subst) {
-                        Expr::Call(op, children) => {
+                        Expr::Call(_, op, children) => {
                             head.push(Action::Delete(op, children));
                         }
                         _ => panic!("Expected call in delete"),
                     }
                 }
+                NormAction::Subsume(expr) => {
+                    let new_expr = expr.to_expr();
+                    new_expr.map(&mut |subexpr| {
+                        if let Expr::Var(_, v) = subexpr {
+                            used.insert(*v);
+                        }
+                        subexpr.clone()
+                    });
+                    match new_expr.subst(subst) {
+                        Expr::Call(_, op, children) => {
+                            head.push(Action::Subsume(op, children));
+                        }
+                        _ => panic!("Expected call in subsume"),
+                    }
+                }
                 NormAction::Union(lhs, rhs) => {
-                    let new_lhs = subst.get(lhs).unwrap_or(&Expr::Var(*lhs)).clone();
-                    let new_rhs = subst.get(rhs).unwrap_or(&Expr::Var(*rhs)).clone();
+                    let new_lhs = subst
+                        .get(lhs)
+                        .unwrap_or(&Expr::Var(DUMMY_SPAN, *lhs))
+                        .clone();
+                    let new_rhs = subst
+                        .get(rhs)
+                        .unwrap_or(&Expr::Var(DUMMY_SPAN, *rhs))
+                        .clone();
                     used.insert(*lhs);
                     used.insert(*rhs);
                     head.push(Action::Union(new_lhs, new_rhs));
                 }
+                NormAction::Pin(var) => {
+                    let new_expr = subst
+                        .get(var)
+                        .unwrap_or(&Expr::Var(DUMMY_SPAN, *var))
+                        .clone();
+                    used.insert(*var);
+                    head.push(Action::Pin(new_expr));
+                }
                 NormAction::Panic(msg) => {
                     head.push(Action::Panic(msg.clone()));
                 }
@@ -1166,10 +1640,25 @@ pub struct Rewrite {
     pub conditions: Vec<Fact>,
 }
 
+/// Conditions of a `birewrite` that only guard one of its directions,
+/// on top of the conditions of its [`Rewrite`] that guard both.
+#[derive(Clone, Debug, Default)]
+pub struct DirectedConditions {
+    pub forward: Vec<Fact>,
+    pub backward: Vec<Fact>,
+}
+
+impl DirectedConditions {
+    pub fn is_empty(&self) -> bool {
+        self.forward.is_empty() && self.backward.is_empty()
+    }
+}
+
 impl Rewrite {
-    pub(crate) fn to_sexp(&self, ruleset: Symbol, is_bidirectional: bool) -> Sexp {
+    /// Prints a `birewrite` when given its directed conditions, and a `rewrite` otherwise.
+    pub(crate) fn to_sexp(&self, ruleset: Symbol, directed: Option<&DirectedConditions>) -> Sexp {
         let mut res = vec![
-            Sexp::String(if is_bidirectional {
+            Sexp::String(if directed.is_some() {
                 "birewrite".into()
             } else {
                 "rewrite".into()
@@ -1178,11 +1667,16 @@ impl Rewrite {
             self.rhs.to_sexp(),
         ];
 
-        if !self.conditions.is_empty() {
-            res.push(Sexp::String(":when".into()));
-            res.push(Sexp::List(
-                self.conditions.iter().map(|f| f.to_sexp()).collect(),
-            ));
+        let directed = directed.cloned().unwrap_or_default();
+        for (keyword, conditions) in [
+            (":when", &self.conditions),
+            (":when-forward", &directed.forward),
+            (":when-backward", &directed.backward),
+        ] {
+            if !conditions.is_empty() {
+                res.push(Sexp::String(keyword.into()));
+                res.push(Sexp::List(conditions.iter().map(|f| f.to_sexp()).collect()));
+            }
         }
 
         if ruleset != "".into() {
@@ -1195,6 +1689,6 @@ impl Rewrite {
 
 impl Display for Rewrite {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_sexp("".into(), false))
+        write!(f, "{}", self.to_sexp("".into(), None))
     }
 }
@@ -1,22 +1,28 @@
 use hashbrown::hash_map::Entry;
 
-use crate::ast::Symbol;
+use crate::ast::{Expr, FunctionDecl, Symbol};
 use crate::termdag::{Term, TermDag};
-use crate::util::HashMap;
+use crate::util::{HashMap, HashSet};
 use crate::{ArcSort, EGraph, Function, Id, Value};
 
 pub type Cost = usize;
 
-#[derive(Debug)]
-pub(crate) struct Node<'a> {
-    sym: Symbol,
-    inputs: &'a [Value],
+/// Combines the costs of a call's children into the cost of the call.
+pub type CostFn<'a> = &'a dyn Fn(&FunctionDecl, &[Cost]) -> Cost;
+
+/// The cost model used by [`EGraph::extract`]: the function's declared cost,
+/// 1 by default, plus the cost of each child.
+pub fn declared_cost(decl: &FunctionDecl, children: &[Cost]) -> Cost {
+    children.iter().fold(decl.cost.unwrap_or(1), |cost, child| {
+        cost.saturating_add(*child)
+    })
 }
 
 pub struct Extractor<'a> {
     pub costs: HashMap<Id, (Cost, Term)>,
     ctors: Vec<Symbol>,
     egraph: &'a EGraph,
+    cost_fn: CostFn<'a>,
 }
 
 impl EGraph {
@@ -30,6 +36,22 @@ impl EGraph {
         None
     }
 
+    /// Counts the e-nodes in the e-class of `value`, leaving out subsumed ones.
+    /// Values of primitive sorts have no e-class, so this is 0 for them.
+    pub fn eclass_size(&self, value: Value) -> usize {
+        let Some((_sort, id)) = self.value_to_id(value) else {
+            return 0;
+        };
+        self.functions
+            .values()
+            .filter(|func| func.schema.output.name() == value.tag)
+            .flat_map(|func| func.nodes.iter())
+            .filter(|(_inputs, output)| {
+                !output.subsumed && self.find(Id::from(output.value.bits as usize)) == id
+            })
+            .count()
+    }
+
     pub fn extract(&self, value: Value, termdag: &mut TermDag, arcsort: &ArcSort) -> (Cost, Term) {
         let extractor = Extractor::new(self, termdag);
         extractor
@@ -56,47 +78,85 @@ impl EGraph {
             })
     }
 
-    pub fn extract_variants(
-        &mut self,
+    /// Extracts the best term for `value` under a different cost model than the
+    /// declared costs, without changing the functions.
+    /// `extract_with(value, declared_cost)` is the same as the default extraction.
+    pub fn extract_with(
+        &self,
+        value: Value,
+        cost_fn: impl Fn(&FunctionDecl, &[Cost]) -> Cost,
+    ) -> (Cost, Expr) {
+        let mut termdag = TermDag::default();
+        let sort = self.get_sort(&value).unwrap();
+        let extractor = Extractor::with_cost_fn(self, &mut termdag, &cost_fn);
+        let (cost, term) = extractor
+            .find_best(value, &mut termdag, sort)
+            .unwrap_or_else(|| panic!("No cost for {:?}", value));
+        (cost, termdag.term_to_expr(&term))
+    }
+
+    /// Extracts up to `n` distinct terms for the e-class of `value`, cheapest first.
+    /// Each one has a different e-node at the root with the best terms below it,
+    /// so fewer than `n` are returned when the e-class doesn't have that many.
+    pub fn extract_variants(&self, value: Value, n: usize) -> Vec<(Cost, Expr)> {
+        let mut termdag = TermDag::default();
+        self.extract_variants_with_termdag(value, n, &mut termdag)
+            .into_iter()
+            .map(|(cost, term)| (cost, termdag.term_to_expr(&term)))
+            .collect()
+    }
+
+    pub(crate) fn extract_variants_with_termdag(
+        &self,
         value: Value,
         limit: usize,
         termdag: &mut TermDag,
-    ) -> Vec<Term> {
-        let (tag, id) = self.value_to_id(value).unwrap();
-        let output_value = &Value::from_id(tag, id);
+    ) -> Vec<(Cost, Term)> {
         let ext = &Extractor::new(self, termdag);
-        ext.ctors
+        let Some((_tag, id)) = self.value_to_id(value) else {
+            // a primitive value is its own only term
+            let sort = self.get_sort(&value).unwrap();
+            return ext.find_best(value, termdag, sort).into_iter().take(limit).collect();
+        };
+        let mut variants: Vec<(Cost, Term)> = ext
+            .ctors
             .iter()
             .flat_map(|&sym| {
                 let func = &self.functions[&sym];
                 if !func.schema.output.is_eq_sort() {
                     return vec![];
                 }
-                assert!(func.schema.output.is_eq_sort());
 
                 func.nodes
                     .iter()
-                    .filter_map(|(inputs, output)| {
-                        (&output.value == output_value).then(|| {
-                            let node = Node { sym, inputs };
-                            ext.expr_from_node(&node, termdag).expect(
-                                "extract_variants should be called after extractor initialization",
-                            )
-                        })
+                    .filter(|(_inputs, output)| !output.subsumed && ext.find(&output.value) == id)
+                    .filter_map(|(inputs, _output)| {
+                        let (terms, cost) = ext.node_total_cost(func, inputs, termdag)?;
+                        Some((cost, termdag.app(sym, terms)))
                     })
                     .collect()
             })
-            .take(limit)
-            .collect()
+            .collect();
+        // the sort is stable, so ties stay in constructor name order
+        variants.sort_by_key(|(cost, _term)| *cost);
+        let mut seen = HashSet::default();
+        variants.retain(|(_cost, term)| seen.insert(term.clone()));
+        variants.truncate(limit);
+        variants
     }
 }
 
 impl<'a> Extractor<'a> {
     pub fn new(egraph: &'a EGraph, termdag: &mut TermDag) -> Self {
+        Self::with_cost_fn(egraph, termdag, &declared_cost)
+    }
+
+    pub fn with_cost_fn(egraph: &'a EGraph, termdag: &mut TermDag, cost_fn: CostFn<'a>) -> Self {
         let mut extractor = Extractor {
             costs: HashMap::default(),
             egraph,
             ctors: vec![],
+            cost_fn,
         };
 
         // only consider "extractable" functions
@@ -108,21 +168,14 @@ impl<'a> Extractor<'a> {
                 .cloned(),
         );
 
+        // visit constructors in name order so ties are broken the same way every run
+        extractor.ctors.sort_by_key(|sym| sym.as_str());
+
         log::debug!("Extracting from ctors: {:?}", extractor.ctors);
         extractor.find_costs(termdag);
         extractor
     }
 
-    fn expr_from_node(&self, node: &Node, termdag: &mut TermDag) -> Option<Term> {
-        let mut children = vec![];
-        for value in node.inputs {
-            let arcsort = self.egraph.get_sort(value).unwrap();
-            children.push(self.find_best(*value, termdag, arcsort)?.1)
-        }
-
-        Some(termdag.app(node.sym, children))
-    }
-
     pub fn find_best(
         &self,
         value: Value,
@@ -140,26 +193,31 @@ impl<'a> Extractor<'a> {
     }
 
     fn node_total_cost(
-        &mut self,
+        &self,
         function: &Function,
         children: &[Value],
         termdag: &mut TermDag,
     ) -> Option<(Vec<Term>, Cost)> {
-        let mut cost = function.decl.cost.unwrap_or(1);
         let types = &function.schema.input;
         let mut terms: Vec<Term> = vec![];
+        let mut costs: Vec<Cost> = vec![];
         for (ty, value) in types.iter().zip(children) {
             let (term_cost, term) = self.find_best(*value, termdag, ty)?;
             terms.push(term.clone());
-            cost = cost.saturating_add(term_cost);
+            costs.push(term_cost);
         }
-        Some((terms, cost))
+        Some((terms, (self.cost_fn)(&function.decl, &costs)))
     }
 
     fn find(&self, value: &Value) -> Id {
         self.egraph.find(Id::from(value.bits as usize))
     }
 
+    /// Computes the best term for each e-class.
+    ///
+    /// Among terms of equal cost, the one whose root constructor name is
+    /// smallest is kept. Remaining ties go to the node found first, iterating
+    /// over constructors by name and over each table in insertion order.
     fn find_costs(&mut self, termdag: &mut TermDag) {
         let mut did_something = true;
         while did_something {
@@ -168,7 +226,8 @@ impl<'a> Extractor<'a> {
             for sym in self.ctors.clone() {
                 let func = &self.egraph.functions[&sym];
                 if func.schema.output.is_eq_sort() {
-                    for (inputs, output) in func.nodes.iter() {
+                    for (inputs, output) in func.nodes.iter().filter(|(_, output)| !output.subsumed)
+                    {
                         if let Some((term_inputs, new_cost)) =
                             self.node_total_cost(func, inputs, termdag)
                         {
@@ -181,7 +240,12 @@ impl<'a> Extractor<'a> {
                                     e.insert(make_new_pair());
                                 }
                                 Entry::Occupied(mut e) => {
-                                    if new_cost < e.get().0 {
+                                    let (old_cost, old_term) = e.get();
+                                    let better = new_cost < *old_cost
+                                        || (new_cost == *old_cost
+                                            && matches!(old_term, Term::App(old_sym, _)
+                                                if sym.as_str() < old_sym.as_str()));
+                                    if better {
                                         did_something = true;
                                         e.insert(make_new_pair());
                                     }
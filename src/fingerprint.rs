@@ -0,0 +1,111 @@
+use std::hash::{BuildHasher as _, Hash, Hasher};
+
+use crate::*;
+
+impl EGraph {
+    /// Hashes the e-graph without depending on the ids of its e-classes, so that
+    /// e-graphs that are the same up to renaming e-classes get the same fingerprint.
+    ///
+    /// E-classes are told apart by repeatedly hashing the rows that produce them,
+    /// until that stops distinguishing any more e-classes.
+    /// As with any hash, different e-graphs may still collide.
+    pub fn structural_fingerprint(&self) -> u64 {
+        let mut colors: HashMap<Id, u64> = HashMap::default();
+        let mut num_colors = 0;
+        loop {
+            let mut rows_by_class: HashMap<Id, Vec<u64>> = HashMap::default();
+            for function in self.functions.values() {
+                if !function.schema.output.is_eq_sort() {
+                    continue;
+                }
+                for (inputs, output) in function.nodes.iter() {
+                    let row = self.row_fingerprint(function, inputs, &colors);
+                    let class = self.find(Id::from(output.value.bits as usize));
+                    rows_by_class.entry(class).or_default().push(row);
+                }
+            }
+
+            let new_colors: HashMap<Id, u64> = rows_by_class
+                .into_iter()
+                .map(|(class, mut rows)| {
+                    rows.sort_unstable();
+                    rows.push(colors.get(&class).copied().unwrap_or(0));
+                    (class, hash_u64s(&rows))
+                })
+                .collect();
+            let new_num_colors = new_colors.values().collect::<HashSet<_>>().len();
+            colors = new_colors;
+            if new_num_colors <= num_colors {
+                break;
+            }
+            num_colors = new_num_colors;
+        }
+
+        let mut rows: Vec<u64> = self
+            .functions
+            .values()
+            .flat_map(|function| {
+                let colors = &colors;
+                function.nodes.iter().map(move |(inputs, output)| {
+                    let row = self.row_fingerprint(function, inputs, colors);
+                    let output =
+                        self.value_fingerprint(&function.schema.output, output.value, colors);
+                    hash_u64s(&[row, output])
+                })
+            })
+            .collect();
+        rows.sort_unstable();
+        hash_u64s(&rows)
+    }
+
+    fn row_fingerprint(
+        &self,
+        function: &Function,
+        inputs: &[Value],
+        colors: &HashMap<Id, u64>,
+    ) -> u64 {
+        let mut hashes = vec![hash_str(function.decl.name.as_str())];
+        hashes.extend(
+            function
+                .schema
+                .input
+                .iter()
+                .zip(inputs)
+                .map(|(sort, value)| self.value_fingerprint(sort, *value, colors)),
+        );
+        hash_u64s(&hashes)
+    }
+
+    /// Replaces e-class ids with their colors, including inside containers.
+    /// Primitives are hashed by the term they print as, since their bits can be
+    /// indices into tables (like the string table) that differ between e-graphs.
+    fn value_fingerprint(&self, sort: &ArcSort, value: Value, colors: &HashMap<Id, u64>) -> u64 {
+        if sort.is_eq_sort() {
+            let class = self.find(Id::from(value.bits as usize));
+            colors.get(&class).copied().unwrap_or(0)
+        } else if sort.is_container_sort() {
+            let mut inner: Vec<u64> = sort
+                .inner_values(&value)
+                .into_iter()
+                .map(|(sort, value)| self.value_fingerprint(sort, value, colors))
+                .collect();
+            inner.push(hash_str(sort.name().as_str()));
+            hash_u64s(&inner)
+        } else {
+            let (_, expr) = sort.make_expr(self, value);
+            hash_str(&expr.to_string())
+        }
+    }
+}
+
+fn hash_str(s: &str) -> u64 {
+    let mut hasher = BuildHasher::default().build_hasher();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn hash_u64s(hashes: &[u64]) -> u64 {
+    let mut hasher = BuildHasher::default().build_hasher();
+    hashes.hash(&mut hasher);
+    hasher.finish()
+}
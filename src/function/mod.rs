@@ -43,6 +43,9 @@ pub enum MergeFn {
 pub struct TupleOutput {
     pub value: Value,
     pub timestamp: u32,
+    /// Subsumed rows are kept for lookups and congruence,
+    /// but rules don't match them and extraction skips them.
+    pub subsumed: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -71,13 +74,18 @@ impl Function {
         for s in &decl.schema.input {
             input.push(match egraph.desugar.type_info.sorts.get(s) {
                 Some(sort) => sort.clone(),
-                None => return Err(Error::TypeError(TypeError::Unbound(*s))),
+                None => return Err(Error::TypeError(TypeError::Unbound(*s, DUMMY_SPAN))),
             })
         }
 
         let output = match egraph.desugar.type_info.sorts.get(&decl.schema.output) {
             Some(sort) => sort.clone(),
-            None => return Err(Error::TypeError(TypeError::Unbound(decl.schema.output))),
+            None => {
+                return Err(Error::TypeError(TypeError::Unbound(
+                    decl.schema.output,
+                    DUMMY_SPAN,
+                )))
+            }
         };
 
         let merge_vals = if let Some(merge_expr) = &decl.merge {
@@ -217,6 +225,10 @@ impl Function {
         res
     }
 
+    pub(crate) fn subsume(&mut self, ks: &[Value]) -> bool {
+        self.nodes.subsume(ks)
+    }
+
     pub(crate) fn clear_updates(&mut self) -> usize {
         mem::take(&mut self.updates)
     }
@@ -377,6 +389,7 @@ impl Function {
         };
 
         let mut out_val = out.value;
+        let subsumed = out.subsumed;
         scratch.clear();
         scratch.extend(args.iter().copied());
 
@@ -420,6 +433,9 @@ impl Function {
                 out_val
             }
         });
+        if subsumed {
+            self.nodes.subsume(scratch);
+        }
         if let Some((inputs, _)) = self.nodes.get_index(i) {
             if inputs != &scratch[..] {
                 scratch.clear();
@@ -149,6 +149,7 @@ impl Table {
             if next == prev.value {
                 return;
             }
+            let subsumed = prev.subsumed;
             inp.stale_at = ts;
             self.n_stale += 1;
             let k = mem::take(&mut inp.data);
@@ -158,6 +159,7 @@ impl Table {
                 TupleOutput {
                     value: next,
                     timestamp: ts,
+                    subsumed,
                 },
             ));
             *off = new_offset;
@@ -169,6 +171,7 @@ impl Table {
             TupleOutput {
                 value: on_merge(None),
                 timestamp: ts,
+                subsumed: false,
             },
         ));
         self.table.insert(
@@ -226,6 +229,19 @@ impl Table {
         true
     }
 
+    /// Mark the entry for the given inputs as subsumed, returns whether there
+    /// was such an entry. The timestamp is unchanged, since subsuming a row
+    /// can only take away matches.
+    pub(crate) fn subsume(&mut self, inp: &[Value]) -> bool {
+        let hash = hash_values(inp);
+        if let Some(TableOffset { off, .. }) = self.table.get(hash, search_for!(self, hash, inp)) {
+            self.vals[*off].1.subsumed = true;
+            true
+        } else {
+            false
+        }
+    }
+
     /// Returns the entries at the given index if the entry is live and the index in bounds.
     pub(crate) fn get_index(&self, i: usize) -> Option<(&[Value], &TupleOutput)> {
         let (inp, out) = self.vals.get(i)?;
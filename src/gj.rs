@@ -32,6 +32,10 @@ enum Instr<'a> {
         args: Vec<AtomTerm>,
         check: bool, // check or assign to output variable
     },
+    Absent {
+        function: Symbol,
+        args: Vec<AtomTerm>,
+    },
 }
 
 // FIXME @mwillsey awful name, bad bad bad
@@ -98,6 +102,9 @@ impl<'a> std::fmt::Display for Instr<'a> {
             Instr::Call { prim, args, check } => {
                 writeln!(f, " Call {:?} {:?} {:?}", prim, args, check)?;
             }
+            Instr::Absent { function, args } => {
+                writeln!(f, " Absent {} {:?}", function, args)?;
+            }
         }
         Ok(())
     }
@@ -252,7 +259,7 @@ impl<'b> Context<'b> {
                     })
                 }
 
-                if let Some(res) = prim.apply(&values) {
+                if let Some(res) = prim.apply_with_egraph(&values, self.egraph) {
                     match out {
                         AtomTerm::Var(v) => {
                             let i = self.query.vars.get_index_of(v).unwrap();
@@ -286,6 +293,24 @@ impl<'b> Context<'b> {
 
                 Ok(())
             }
+            Instr::Absent { function, args } => {
+                let values: Vec<Value> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        AtomTerm::Var(v) => {
+                            let i = self.query.vars.get_index_of(v).unwrap();
+                            self.tuple[i]
+                        }
+                        AtomTerm::Value(val) => *val,
+                        AtomTerm::Global(g) => self.egraph.global_bindings.get(g).unwrap().1,
+                    })
+                    .collect();
+
+                if self.egraph.functions[function].get(&values).is_some() {
+                    return Ok(());
+                }
+                self.eval(tries, program, stage.next(), f)
+            }
         }
     }
 }
@@ -568,6 +593,14 @@ impl EGraph {
             }
         }
 
+        // absent rows are checked last, once all their arguments are bound
+        for atom in &query.query.absent {
+            program.push(Instr::Absent {
+                function: atom.head,
+                args: atom.args.clone(),
+            });
+        }
+
         let resulting_program = Program(program);
         self.sanity_check_program(&resulting_program, query);
 
@@ -616,6 +649,14 @@ impl EGraph {
                         }
                     }
                 }
+                Instr::Absent { args, .. } => {
+                    for a in args {
+                        if let AtomTerm::Var(v) = a {
+                            let i = query.vars.get_index_of(v).unwrap();
+                            assert!(tuple_valid[i], "{instr}");
+                        }
+                    }
+                }
             }
         }
     }
@@ -867,7 +908,8 @@ impl<'a> TrieAccess<'a> {
         ixs.iter().copied().filter_map(|ix| {
             let ix = ix as usize;
             let (inp, out) = self.function.nodes.get_index(ix)?;
-            if self.timestamp_range.contains(&out.timestamp)
+            if !out.subsumed
+                && self.timestamp_range.contains(&out.timestamp)
                 && self.constraints.iter().all(|c| c.check(inp, out))
             {
                 Some(ix)
@@ -883,7 +925,8 @@ impl<'a> TrieAccess<'a> {
         let mut map = SparseMap::default();
         let mut insert = |i: usize, tup: &[Value], out: &TupleOutput, val: Value| {
             use hashbrown::hash_map::Entry;
-            if self.timestamp_range.contains(&out.timestamp)
+            if !out.subsumed
+                && self.timestamp_range.contains(&out.timestamp)
                 && self.constraints.iter().all(|c| c.check(tup, out))
             {
                 match map.entry(val) {
@@ -1,5 +1,6 @@
 pub mod ast;
 mod extract;
+mod fingerprint;
 mod function;
 mod gj;
 mod serialize;
@@ -13,10 +14,11 @@ mod value;
 
 use ast::desugar::Desugar;
 use extract::Extractor;
+pub use extract::{declared_cost, Cost};
 use hashbrown::hash_map::Entry;
 use index::ColumnIndex;
 use instant::{Duration, Instant};
-pub use serialize::SerializeConfig;
+pub use serialize::{SerializeConfig, SerializedFunction};
 use sort::*;
 pub use termdag::{Term, TermDag, TermId};
 use thiserror::Error;
@@ -24,8 +26,9 @@ use thiserror::Error;
 use symbolic_expressions::Sexp;
 
 use ast::*;
-pub use typechecking::{TypeInfo, UNIT_SYM};
+pub use typechecking::{FuncType, TypeInfo, UNIT_SYM};
 
+use std::cell::RefCell;
 use std::fmt::{Display, Formatter, Write};
 use std::fs::File;
 use std::hash::Hash;
@@ -47,14 +50,27 @@ use gj::*;
 use unionfind::*;
 use util::*;
 
-use crate::typechecking::TypeError;
+use crate::typechecking::{ranked_type_errors, TypeError};
 
 pub type Subst = IndexMap<Symbol, Value>;
 
+/// A function on values that is computed rather than stored in a table,
+/// like `+` on `i64`. Register one with [`EGraph::add_primitive`].
 pub trait PrimitiveLike {
+    /// The name the primitive is called by. Primitives may share a name
+    /// as long as they accept different argument sorts.
     fn name(&self) -> Symbol;
+    /// Given the sorts of the arguments, the sort of the result,
+    /// or `None` if this primitive doesn't apply to them.
     fn accept(&self, types: &[ArcSort]) -> Option<ArcSort>;
+    /// Computes the result for arguments of sorts that `accept` allowed.
+    /// `None` means there is no result, so the query or action doesn't go through.
     fn apply(&self, values: &[Value]) -> Option<Value>;
+    /// Like `apply`, for primitives that also read the e-graph, such as `count-rows`.
+    /// Queries and actions call this one, which is `apply` unless overridden.
+    fn apply_with_egraph(&self, values: &[Value], _egraph: &EGraph) -> Option<Value> {
+        self.apply(values)
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -63,6 +79,47 @@ pub struct RunReport {
     pub search_time: Duration,
     pub apply_time: Duration,
     pub rebuild_time: Duration,
+    /// How many matches the search phase found for each rule that was run.
+    pub num_matches_per_rule: HashMap<Symbol, usize>,
+}
+
+/// What one rule has done across all the runs so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleStats {
+    pub name: Symbol,
+    /// How many matches of the rule have been applied.
+    pub matches: usize,
+    pub times_banned: usize,
+    pub search_time: Duration,
+    pub apply_time: Duration,
+}
+
+/// The [`RuleStats`] of the rules declared in a ruleset, sorted by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RulesetStats {
+    pub rules: Vec<RuleStats>,
+}
+
+impl RulesetStats {
+    pub fn total_matches(&self) -> usize {
+        self.rules.iter().map(|rule| rule.matches).sum()
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.rules
+            .iter()
+            .map(|rule| rule.search_time + rule.apply_time)
+            .sum()
+    }
+}
+
+/// What kind of sort a sort is, as listed by [`EGraph::sorts`].
+/// A sort that is neither an eq sort nor a container sort is a primitive one, like `i64`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortInfo {
+    pub name: Symbol,
+    pub is_eq_sort: bool,
+    pub is_container_sort: bool,
 }
 
 /// A report of the results of an extract action.
@@ -72,10 +129,14 @@ pub enum ExtractReport {
         termdag: TermDag,
         cost: usize,
         expr: Term,
+        /// The number of e-nodes in the extracted e-class.
+        eclass_size: usize,
     },
     Variants {
         termdag: TermDag,
         variants: Vec<Term>,
+        /// The number of e-nodes in the extracted e-class.
+        eclass_size: usize,
     },
 }
 
@@ -86,6 +147,13 @@ impl RunReport {
             search_time: self.search_time + other.search_time,
             apply_time: self.apply_time + other.apply_time,
             rebuild_time: self.rebuild_time + other.rebuild_time,
+            num_matches_per_rule: {
+                let mut res = self.num_matches_per_rule.clone();
+                for (rule, matches) in &other.num_matches_per_rule {
+                    *res.entry(*rule).or_default() += matches;
+                }
+                res
+            },
         }
     }
 }
@@ -159,6 +227,39 @@ impl PrimitiveLike for SimplePrimitive {
     }
 }
 
+/// Counts the live rows of the function named by its string argument.
+/// It reads the tables as they are when it runs, so counts used in
+/// rules can go out of date as the e-graph grows.
+pub(crate) struct CountRows {
+    pub(crate) string: Arc<StringSort>,
+    pub(crate) i64: Arc<I64Sort>,
+}
+
+impl PrimitiveLike for CountRows {
+    fn name(&self) -> Symbol {
+        "count-rows".into()
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [name] if name.name() == self.string.name() => Some(self.i64.clone()),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, _values: &[Value]) -> Option<Value> {
+        // the rows can't be counted without the e-graph
+        None
+    }
+
+    fn apply_with_egraph(&self, values: &[Value], egraph: &EGraph) -> Option<Value> {
+        let name = String::load(&self.string, &values[0]);
+        // searched by name, since making a Symbol would intern every string queried
+        let (_, function) = egraph.functions.iter().find(|(f, _)| f.as_str() == name)?;
+        (function.nodes.len() as i64).store(&self.i64)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 pub enum CompilerPassStop {
     Desugar,
@@ -217,13 +318,36 @@ pub struct EGraph {
     pub node_limit: usize,
     pub fact_directory: Option<PathBuf>,
     pub seminaive: bool,
+    /// When set, `panic` actions return [`Error::UserPanic`] instead of panicking.
+    pub recoverable_panics: bool,
+    /// When set, each merge is also computed with `old` and `new` swapped,
+    /// returning [`Error::MergeOrderError`] if the results differ.
+    pub check_merge_order: bool,
+    /// Bounds how long saturating until some facts hold may take,
+    /// as `calc` does, before failing with [`Error::CheckTimeout`].
+    pub check_timeout: Option<Duration>,
+    // when the innermost running timeout schedule has to stop
+    schedule_deadline: Option<Instant>,
+    // when the innermost saturation bounded by `check_timeout` has to fail,
+    // along with that timeout
+    check_deadline: Option<(Instant, Duration)>,
+    // the first panic or merge order error raised while applying rules,
+    // reported after the schedule
+    rule_error: Option<Error>,
     // sort, value, and timestamp
     pub global_bindings: HashMap<Symbol, (ArcSort, Value, u32)>,
     extract_report: Option<ExtractReport>,
     run_report: Option<RunReport>,
     msgs: Vec<String>,
+    warnings: Vec<String>,
+    // snapshot restored by `reset_to_prelude`
+    prelude: Option<Box<EGraph>>,
+    // callbacks registered with `on_command`, kept across push, pop and resets
+    command_hooks: Vec<CommandHook>,
 }
 
+type CommandHook = Rc<RefCell<dyn FnMut(&NormCommand, &EGraph)>>;
+
 #[derive(Clone, Debug)]
 struct Rule {
     query: CompiledQuery,
@@ -254,9 +378,18 @@ impl Default for EGraph {
             test_proofs: false,
             fact_directory: None,
             seminaive: true,
+            recoverable_panics: false,
+            check_merge_order: false,
+            check_timeout: None,
+            schedule_deadline: None,
+            check_deadline: None,
+            rule_error: None,
             extract_report: None,
             run_report: None,
             msgs: Default::default(),
+            warnings: Default::default(),
+            prelude: None,
+            command_hooks: vec![],
         };
         egraph.rulesets.insert("".into(), Default::default());
         egraph
@@ -283,7 +416,9 @@ impl EGraph {
                 let extract_report = self.extract_report.clone();
                 let run_report = self.run_report.clone();
                 let messages = self.msgs.clone();
+                let command_hooks = std::mem::take(&mut self.command_hooks);
                 *self = e;
+                self.command_hooks = command_hooks;
                 if let Some(report) = extract_report {
                     self.extract_report = Some(report);
                 }
@@ -409,13 +544,13 @@ impl EGraph {
             new_unions += unions;
         }
         for (func, merges) in deferred_merges {
-            new_unions += self.apply_merges(func, &merges);
+            new_unions += self.apply_merges(func, &merges)?;
         }
 
         Ok(new_unions)
     }
 
-    fn apply_merges(&mut self, func: Symbol, merges: &[DeferredMerge]) -> usize {
+    fn apply_merges(&mut self, func: Symbol, merges: &[DeferredMerge]) -> Result<usize, Error> {
         let mut stack = Vec::new();
         let mut function = self.functions.get_mut(&func).unwrap();
         let n_unions = self.unionfind.n_unions();
@@ -439,9 +574,39 @@ impl EGraph {
                 stack.clear();
                 function = self.functions.get_mut(&func).unwrap();
                 function.insert(inputs, merged, self.timestamp);
+                self.verify_merge_order(func, prog, inputs, *old, *new)?;
+                function = self.functions.get_mut(&func).unwrap();
             }
         }
-        self.unionfind.n_unions() - n_unions + function.clear_updates()
+        Ok(self.unionfind.n_unions() - n_unions + function.clear_updates())
+    }
+
+    /// If `check_merge_order` is set, runs the merge again with `old` and `new`
+    /// swapped and errors if it doesn't produce the output stored for `inputs`,
+    /// up to the union-find.
+    pub(crate) fn verify_merge_order(
+        &mut self,
+        func: Symbol,
+        prog: &Program,
+        inputs: &[Value],
+        old: Value,
+        new: Value,
+    ) -> Result<(), Error> {
+        if !self.check_merge_order {
+            return Ok(());
+        }
+        // read the output first, in case the merge writes to its own function
+        let stored = self.functions[&func]
+            .get(inputs)
+            .expect("merged outputs should be stored");
+        let mut stack = vec![];
+        self.run_actions(&mut stack, &[new, old], prog, true)?;
+        let swapped = stack.pop().expect("merges should produce a value");
+        if self.bad_find_value(swapped) == self.bad_find_value(stored) {
+            Ok(())
+        } else {
+            Err(Error::MergeOrderError(func, old, new))
+        }
     }
 
     pub fn declare_function(&mut self, decl: &FunctionDecl) -> Result<(), Error> {
@@ -457,6 +622,15 @@ impl EGraph {
         Ok(())
     }
 
+    /// The field names a datatype constructor was declared with, if any,
+    /// with `None` for positional fields.
+    pub fn constructor_fields(&self, name: Symbol) -> Option<&[Option<Symbol>]> {
+        self.desugar
+            .constructor_fields
+            .get(&name)
+            .map(|fields| fields.as_slice())
+    }
+
     pub fn declare_constructor(
         &mut self,
         variant: Variant,
@@ -486,7 +660,11 @@ impl EGraph {
         match lit {
             Literal::Int(i) => i.store(&self.desugar.type_info.get_sort()).unwrap(),
             Literal::F64(f) => f.store(&self.desugar.type_info.get_sort()).unwrap(),
-            Literal::String(s) => s.store(&self.desugar.type_info.get_sort()).unwrap(),
+            Literal::String(s) => s
+                .to_string()
+                .store(&self.desugar.type_info.get_sort())
+                .unwrap(),
+            Literal::Bool(b) => b.store(&self.desugar.type_info.get_sort()).unwrap(),
             Literal::Unit => ().store(&self.desugar.type_info.get_sort()).unwrap(),
         }
     }
@@ -496,7 +674,10 @@ impl EGraph {
         sym: Symbol,
         n: usize,
     ) -> Result<(Vec<(Term, Term)>, TermDag), Error> {
-        let f = self.functions.get(&sym).ok_or(TypeError::Unbound(sym))?;
+        let f = self
+            .functions
+            .get(&sym)
+            .ok_or(TypeError::Unbound(sym, DUMMY_SPAN))?;
         let schema = f.schema.clone();
         let nodes = f
             .nodes
@@ -567,13 +748,66 @@ impl EGraph {
         Ok(())
     }
 
-    pub fn print_size(&mut self, sym: Symbol) -> Result<(), Error> {
-        let f = self.functions.get(&sym).ok_or(TypeError::Unbound(sym))?;
-        log::info!("Function {} has size {}", sym, f.nodes.len());
-        self.print_msg(f.nodes.len().to_string());
+    /// Prints up to `n` tuples of every function whose output has the given sort.
+    pub fn print_functions_by_sort(&mut self, sort: Symbol, n: usize) -> Result<(), Error> {
+        if !self.desugar.type_info.sorts.contains_key(&sort) {
+            return Err(TypeError::UndefinedSort(sort).into());
+        }
+        let mut names: Vec<Symbol> = self
+            .functions
+            .iter()
+            .filter(|(_name, f)| f.schema.output.name() == sort)
+            .map(|(name, _f)| *name)
+            .collect();
+        names.sort_by_key(|name| name.as_str());
+        for name in names {
+            self.print_msg(format!("{name}:"));
+            self.print_function(name, n)?;
+        }
         Ok(())
     }
 
+    /// Prints the number of rows of a function, or of every function
+    /// from largest to smallest when no function is given.
+    pub fn print_size(&mut self, sym: Option<Symbol>) -> Result<(), Error> {
+        let Some(sym) = sym else {
+            let mut sizes: Vec<(Symbol, usize)> = self
+                .functions
+                .iter()
+                .map(|(name, f)| (*name, f.nodes.len()))
+                .collect();
+            sizes.sort_by_key(|(name, size)| (std::cmp::Reverse(*size), name.as_str()));
+            for (name, size) in sizes {
+                self.print_msg(format!("{name}: {size}"));
+            }
+            return Ok(());
+        };
+        let size = self.function_size(Some(sym))?;
+        log::info!("Function {} has size {}", sym, size);
+        self.print_msg(size.to_string());
+        Ok(())
+    }
+
+    /// The number of rows of a function, or the total over all functions
+    /// when no function is given, leaving out the temporary ones made by desugaring.
+    pub fn function_size(&self, sym: Option<Symbol>) -> Result<usize, Error> {
+        match sym {
+            Some(sym) => {
+                let f = self
+                    .functions
+                    .get(&sym)
+                    .ok_or(TypeError::Unbound(sym, DUMMY_SPAN))?;
+                Ok(f.nodes.len())
+            }
+            None => Ok(self
+                .functions
+                .iter()
+                .filter(|(name, _)| !self.is_temp_name(name.to_string()))
+                .map(|(_, f)| f.nodes.len())
+                .sum()),
+        }
+    }
+
     // returns whether the egraph was updated
     pub fn run_schedule(&mut self, sched: &NormSchedule) -> RunReport {
         match sched {
@@ -581,6 +815,9 @@ impl EGraph {
             NormSchedule::Repeat(limit, sched) => {
                 let mut report = RunReport::default();
                 for _i in 0..*limit {
+                    if self.should_stop_schedule() {
+                        break;
+                    }
                     let rec = self.run_schedule(sched);
                     report = report.union(&rec);
                     if !rec.updated {
@@ -591,51 +828,121 @@ impl EGraph {
             }
             NormSchedule::Saturate(sched) => {
                 let mut report = RunReport::default();
+                // the facts may never hold, so only these saturations are bounded,
+                // and an enclosing one may end sooner
+                let outer_deadline = self.check_deadline;
+                if let Some(timeout) = self.check_timeout.filter(|_| sched.has_until()) {
+                    let deadline = Instant::now() + timeout;
+                    if outer_deadline.map_or(true, |(outer, _)| deadline < outer) {
+                        self.check_deadline = Some((deadline, timeout));
+                    }
+                }
                 loop {
                     let rec = self.run_schedule(sched);
                     report = report.union(&rec);
-                    if !rec.updated {
+                    if !rec.updated || self.should_stop_schedule() || self.past_check_deadline() {
                         break;
                     }
                 }
+                self.check_deadline = outer_deadline;
                 report
             }
             NormSchedule::Sequence(scheds) => {
                 let mut report = RunReport::default();
                 for sched in scheds {
+                    if self.should_stop_schedule() {
+                        break;
+                    }
                     report = report.union(&self.run_schedule(sched));
                 }
                 report
             }
+            NormSchedule::Timeout(duration, sched) => {
+                // an enclosing timeout may end sooner
+                let outer_deadline = self.schedule_deadline;
+                let deadline = Instant::now() + *duration;
+                self.schedule_deadline = Some(outer_deadline.map_or(deadline, |d| d.min(deadline)));
+                let report = self.run_schedule(sched);
+                self.schedule_deadline = outer_deadline;
+                report
+            }
+        }
+    }
+
+    /// Whether the schedule should stop early, because its deadline passed,
+    /// a rule or a rebuild raised an error, or a bounded saturation timed out.
+    fn should_stop_schedule(&self) -> bool {
+        self.stopped_by_error()
+            || self
+                .schedule_deadline
+                .map_or(false, |deadline| Instant::now() >= deadline)
+    }
+
+    fn stopped_by_error(&self) -> bool {
+        self.rule_error.is_some()
+    }
+
+    /// Whether the innermost saturation bounded by `check_timeout` has run
+    /// out of time, recording an [`Error::CheckTimeout`] if so.
+    fn past_check_deadline(&mut self) -> bool {
+        match self.check_deadline {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                self.rule_error.get_or_insert(Error::CheckTimeout(timeout));
+                true
+            }
+            _ => false,
         }
     }
 
     pub fn run_rules_once(&mut self, config: &NormRunConfig, report: &mut RunReport) {
         // first rebuild
         let rebuild_start = Instant::now();
-        let updates = self.rebuild_nofail();
+        let rebuilt = self.rebuild();
+        report.rebuild_time += rebuild_start.elapsed();
+        let updates = match rebuilt {
+            Ok(updates) => updates,
+            // a merge failed, so stop the run and report it
+            Err(err) => {
+                self.rule_error.get_or_insert(err);
+                return;
+            }
+        };
         log::debug!("database size: {}", self.num_tuples());
         log::debug!("Made {updates} updates");
-        report.rebuild_time += rebuild_start.elapsed();
-        self.timestamp += 1;
 
-        let NormRunConfig { ruleset, until } = config;
+        let NormRunConfig {
+            ruleset,
+            until,
+            until_mode,
+            dry_run,
+        } = config;
+        // a dry run adds nothing, so it doesn't need a new timestamp either
+        if !dry_run {
+            self.timestamp += 1;
+        }
 
-        if let Some(facts) = until {
-            if self.check_facts(facts).is_ok() {
+        if let Some(groups) = until {
+            let mut holds = groups.iter().map(|facts| self.check_facts(facts).is_ok());
+            let stop = match until_mode {
+                UntilMode::All => holds.all(|holds| holds),
+                UntilMode::Any => holds.any(|holds| holds),
+            };
+            if stop {
                 log::info!(
                     "Breaking early because of facts:\n {}!",
-                    ListDisplay(facts, "\n")
+                    ListDisplay(groups.iter().flatten(), "\n")
                 );
                 return;
             }
         }
 
-        let subreport = self.step_rules(*ruleset);
+        let subreport = self.step_rules(*ruleset, *dry_run);
         *report = report.union(&subreport);
 
         log::debug!("database size: {}", self.num_tuples());
-        self.timestamp += 1;
+        if !dry_run {
+            self.timestamp += 1;
+        }
 
         if self.num_tuples() > self.node_limit {
             log::warn!("Node limit reached, {} nodes. Stopping!", self.num_tuples());
@@ -647,6 +954,14 @@ impl EGraph {
 
         self.run_rules_once(config, &mut report);
 
+        if config.dry_run {
+            let mut counts: Vec<_> = report.num_matches_per_rule.iter().collect();
+            counts.sort_by_key(|(name, _)| name.as_str());
+            for (name, matches) in counts {
+                self.print_msg(format!("{name}: {matches} matches"));
+            }
+        }
+
         // Report the worst offenders
         log::debug!("Slowest rules:\n{}", {
             let mut msg = String::new();
@@ -678,7 +993,10 @@ impl EGraph {
         report
     }
 
-    fn step_rules(&mut self, ruleset: Symbol) -> RunReport {
+    /// Searches for matches of every rule in the ruleset, then applies them.
+    /// A dry run stops after the search, leaving the e-graph and the rules'
+    /// scheduling state untouched.
+    fn step_rules(&mut self, ruleset: Symbol, dry_run: bool) -> RunReport {
         let n_unions_before = self.unionfind.n_unions();
         // don't ban parent or rebuilding
         let match_limit =
@@ -701,8 +1019,12 @@ impl EGraph {
         // TODO why did I have to copy the rules here for the first for loop?
         let copy_rules = rules.clone();
         let search_start = Instant::now();
+        let check_deadline = self.check_deadline.map(|(deadline, _)| deadline);
         let mut searched = vec![];
         for (name, rule) in copy_rules.iter() {
+            if self.past_check_deadline() {
+                break;
+            }
             let mut all_values = vec![];
             if rule.banned_until <= iteration {
                 let mut fuel = safe_shl(match_limit, rule.times_banned);
@@ -710,7 +1032,9 @@ impl EGraph {
                 self.run_query(&rule.query, rule.todo_timestamp, |values| {
                     assert_eq!(values.len(), rule.query.vars.len());
                     all_values.extend_from_slice(values);
-                    if fuel > 0 {
+                    if check_deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                        Err(())
+                    } else if fuel > 0 {
                         fuel -= 1;
                         Ok(())
                     } else {
@@ -730,6 +1054,24 @@ impl EGraph {
         let search_elapsed = search_start.elapsed();
         report.search_time += search_elapsed;
 
+        for (name, all_values, _time) in &searched {
+            let num_vars = copy_rules[*name].query.vars.len();
+            // queries without variables are applied once
+            let len = if num_vars == 0 {
+                1
+            } else {
+                all_values.len() / num_vars
+            };
+            report.num_matches_per_rule.insert(**name, len);
+        }
+
+        // a timed out search applies nothing, as its matches may be partial
+        if dry_run || self.past_check_deadline() {
+            self.ruleset_iteration.insert(ruleset, iteration);
+            self.rulesets.insert(ruleset, rules);
+            return report;
+        }
+
         let apply_start = Instant::now();
         for (name, all_values, time) in searched {
             let rule = rules.get_mut(name).unwrap();
@@ -758,19 +1100,28 @@ impl EGraph {
             // run one iteration when n == 0
             if num_vars == 0 {
                 rule.matches += 1;
-                // we can ignore results here
+                // we can ignore results here, except for user panics
                 stack.clear();
-                let _ = self.run_actions(stack, &[], &rule.program, true);
+                let res = self.run_actions(stack, &[], &rule.program, true);
+                self.record_rule_error(res);
             } else {
                 for values in all_values.chunks(num_vars) {
                     rule.matches += 1;
-                    // we can ignore results here
+                    // we can ignore results here, except for user panics
                     stack.clear();
-                    let _ = self.run_actions(stack, values, &rule.program, true);
+                    let res = self.run_actions(stack, values, &rule.program, true);
+                    self.record_rule_error(res);
+                    if self.stopped_by_error() || self.past_check_deadline() {
+                        break;
+                    }
                 }
             }
 
             rule.apply_time += rule_apply_start.elapsed();
+            // the first panic or timeout ends the run
+            if self.stopped_by_error() || self.past_check_deadline() {
+                break;
+            }
         }
         self.rulesets.insert(ruleset, rules);
         let apply_elapsed = apply_start.elapsed();
@@ -796,11 +1147,17 @@ impl EGraph {
         rule: ast::Rule,
         ruleset: Symbol,
     ) -> Result<Symbol, Error> {
+        self.desugar.type_info.register_pending_primitives();
         let name = Symbol::from(name);
         let mut ctx = typecheck::Context::new(self);
         let (query0, action0) = ctx
             .typecheck_query(&rule.body, &rule.head)
             .map_err(Error::TypeErrors)?;
+        let conflicts: Vec<String> = ctx
+            .conflicting_literals()
+            .iter()
+            .map(|(a, b)| format!("{a} = {b}"))
+            .collect();
         let query = self.compile_gj_query(query0, &ctx.types);
         let program = self
             .compile_actions(&ctx.types, &action0)
@@ -819,6 +1176,14 @@ impl EGraph {
             search_time: Duration::default(),
             apply_time: Duration::default(),
         };
+        if !conflicts.is_empty() {
+            let warning = format!(
+                "Rule {name} can never match, its body requires {}",
+                ListDisplay(&conflicts, ", ")
+            );
+            log::warn!("{}", warning);
+            self.warnings.push(warning);
+        }
         if let Some(rules) = self.rulesets.get_mut(&ruleset) {
             match rules.entry(name) {
                 Entry::Occupied(_) => panic!("Rule '{name}' was already present"),
@@ -836,6 +1201,7 @@ impl EGraph {
     }
 
     pub fn eval_actions(&mut self, actions: &[Action]) -> Result<(), Error> {
+        self.desugar.type_info.register_pending_primitives();
         let types = Default::default();
         let program = self
             .compile_actions(&types, actions)
@@ -851,6 +1217,7 @@ impl EGraph {
         expected_type: Option<ArcSort>,
         make_defaults: bool,
     ) -> Result<(ArcSort, Value), Error> {
+        self.desugar.type_info.register_pending_primitives();
         let types = Default::default();
         let (t, program) = self
             .compile_expr(&types, expr, expected_type)
@@ -868,27 +1235,58 @@ impl EGraph {
         };
     }
 
+    fn record_rule_error(&mut self, res: Result<(), Error>) {
+        if let Err(err @ (Error::UserPanic(..) | Error::MergeOrderError(..))) = res {
+            self.rule_error.get_or_insert(err);
+        }
+    }
+
     pub fn set_option(&mut self, name: &str, value: Expr) {
         match name {
             "enable_proofs" => {
                 self.proofs_enabled = true;
             }
             "interactive_mode" => {
-                if let Expr::Lit(Literal::Int(i)) = value {
+                if let Expr::Lit(_, Literal::Int(i)) = value {
                     self.interactive_mode = i != 0;
                 } else {
                     panic!("interactive_mode must be an integer");
                 }
             }
             "match_limit" => {
-                if let Expr::Lit(Literal::Int(i)) = value {
+                if let Expr::Lit(_, Literal::Int(i)) = value {
                     self.match_limit = i as usize;
                 } else {
                     panic!("match_limit must be an integer");
                 }
             }
+            "recoverable_panics" => {
+                if let Expr::Lit(_, Literal::Int(i)) = value {
+                    self.recoverable_panics = i != 0;
+                } else {
+                    panic!("recoverable_panics must be an integer");
+                }
+            }
+            "check_merge_order" => {
+                if let Expr::Lit(_, Literal::Int(i)) = value {
+                    self.check_merge_order = i != 0;
+                } else {
+                    panic!("check_merge_order must be an integer");
+                }
+            }
+            "check_timeout" => {
+                if let Expr::Lit(_, Literal::Int(i)) = value {
+                    // in milliseconds, 0 disables the timeout
+                    self.check_timeout = u64::try_from(i)
+                        .ok()
+                        .filter(|&ms| ms > 0)
+                        .map(Duration::from_millis);
+                } else {
+                    panic!("check_timeout must be an integer");
+                }
+            }
             "node_limit" => {
-                if let Expr::Lit(Literal::Int(i)) = value {
+                if let Expr::Lit(_, Literal::Int(i)) = value {
                     self.node_limit = i as usize;
                 } else {
                     panic!("node_limit must be an integer");
@@ -922,6 +1320,44 @@ impl EGraph {
         }
     }
 
+    /// Runs a top-level action. Rebuilding is left to the caller, so a batch
+    /// of actions runs before a single rebuild.
+    fn run_norm_action(&mut self, action: &NormAction) -> Result<(), Error> {
+        match action {
+            NormAction::Let(name, contents) => {
+                let (etype, value) = self.eval_expr(&contents.to_expr(), None, true)?;
+                let present = self
+                    .global_bindings
+                    .insert(*name, (etype, value, self.timestamp));
+                if present.is_some() {
+                    panic!("Variable {name} was already present in global bindings");
+                }
+            }
+            NormAction::LetVar(var1, var2) => {
+                let value = self.global_bindings.get(var2).unwrap();
+                let present = self.global_bindings.insert(*var1, value.clone());
+                if present.is_some() {
+                    panic!("Variable {var1} was already present in global bindings");
+                }
+            }
+            NormAction::LetLit(var, lit) => {
+                let value = self.eval_lit(lit);
+                let etype = self.desugar.type_info.infer_literal(lit);
+                let present = self
+                    .global_bindings
+                    .insert(*var, (etype, value, self.timestamp));
+
+                if present.is_some() {
+                    panic!("Variable {var} was already present in global bindings");
+                }
+            }
+            _ => {
+                self.eval_actions(std::slice::from_ref(&action.to_action()))?;
+            }
+        }
+        Ok(())
+    }
+
     fn run_command(&mut self, command: NCommand, should_run: bool) -> Result<(), Error> {
         let pre_rebuild = Instant::now();
         let rebuild_num = self.rebuild()?;
@@ -961,6 +1397,9 @@ impl EGraph {
             NCommand::RunSchedule(sched) => {
                 if should_run {
                     self.run_report = Some(self.run_schedule(&sched));
+                    if let Some(err) = self.rule_error.take() {
+                        return Err(err);
+                    }
                     log::info!("Ran schedule {}.", sched)
                 } else {
                     log::warn!("Skipping schedule.")
@@ -974,45 +1413,41 @@ impl EGraph {
                     log::warn!("Skipping check.")
                 }
             }
+            NCommand::CheckIn(config, facts) => {
+                if should_run {
+                    self.push();
+                    let report = self.run_schedule(&NormSchedule::Run(config));
+                    // like a separate check, which rebuilds before it runs
+                    let result = match self.rule_error.take() {
+                        Some(err) => Err(err),
+                        None => self.rebuild().and_then(|_| self.check_facts(&facts)),
+                    };
+                    // pop on failure too, so nothing the run did is kept
+                    self.pop()?;
+                    self.run_report = Some(report);
+                    result?;
+                    log::info!("Checked fact {:?} in a pushed scope.", facts);
+                } else {
+                    log::warn!("Skipping check.")
+                }
+            }
             NCommand::CheckProof => log::error!("TODO implement proofs"),
             NCommand::NormAction(action) => {
                 if should_run {
-                    match &action {
-                        NormAction::Let(name, contents) => {
-                            let (etype, value) = self.eval_expr(&contents.to_expr(), None, true)?;
-                            let present = self
-                                .global_bindings
-                                .insert(*name, (etype, value, self.timestamp));
-                            if present.is_some() {
-                                panic!("Variable {name} was already present in global bindings");
-                            }
-                        }
-                        NormAction::LetVar(var1, var2) => {
-                            let value = self.global_bindings.get(var2).unwrap();
-                            let present = self.global_bindings.insert(*var1, value.clone());
-                            if present.is_some() {
-                                panic!("Variable {var1} was already present in global bindings");
-                            }
-                        }
-                        NormAction::LetLit(var, lit) => {
-                            let value = self.eval_lit(lit);
-                            let etype = self.desugar.type_info.infer_literal(lit);
-                            let present = self
-                                .global_bindings
-                                .insert(*var, (etype, value, self.timestamp));
-
-                            if present.is_some() {
-                                panic!("Variable {var} was already present in global bindings");
-                            }
-                        }
-                        _ => {
-                            self.eval_actions(std::slice::from_ref(&action.to_action()))?;
-                        }
-                    }
+                    self.run_norm_action(&action)?;
                 } else {
                     log::warn!("Skipping running {action}.")
                 }
             }
+            NCommand::NormActions(actions) => {
+                if should_run {
+                    for action in &actions {
+                        self.run_norm_action(action)?;
+                    }
+                } else {
+                    log::warn!("Skipping running {} actions.", actions.len())
+                }
+            }
             NCommand::Push(n) => {
                 (0..n).for_each(|_| self.push());
                 log::info!("Pushed {n} levels.")
@@ -1026,9 +1461,17 @@ impl EGraph {
             NCommand::PrintTable(f, n) => {
                 self.print_function(f, n)?;
             }
+            NCommand::PrintBySort(sort, n) => {
+                self.print_functions_by_sort(sort, n)?;
+            }
             NCommand::PrintSize(f) => {
                 self.print_size(f)?;
             }
+            NCommand::PrintFingerprint => {
+                let fingerprint = self.structural_fingerprint();
+                log::info!("E-graph fingerprint: {fingerprint}");
+                self.print_msg(fingerprint.to_string());
+            }
             NCommand::Fail(c) => {
                 let result = self.run_command(*c, should_run);
                 if let Err(e) = result {
@@ -1072,16 +1515,16 @@ impl EGraph {
 
                     let parse = |s: &str| -> Expr {
                         if let Ok(i) = s.parse() {
-                            Expr::Lit(Literal::Int(i))
+                            Expr::Lit(DUMMY_SPAN, Literal::Int(i))
                         } else {
-                            Expr::Lit(Literal::String(s.into()))
+                            Expr::Lit(DUMMY_SPAN, Literal::String(s.into()))
                         }
                     };
 
                     let mut exprs: Vec<Expr> = str_buf.iter().map(|&s| parse(s)).collect();
 
                     actions.push(if is_unit {
-                        Action::Expr(Expr::Call(name, exprs))
+                        Action::Expr(Expr::Call(DUMMY_SPAN, name, exprs))
                     } else {
                         let out = exprs.pop().unwrap();
                         Action::Set(name, exprs, out)
@@ -1090,26 +1533,36 @@ impl EGraph {
                 self.eval_actions(&actions)?;
                 log::info!("Read {} facts into {name} from '{file}'.", actions.len())
             }
-            NCommand::Output { file, exprs } => {
-                let mut filename = self.fact_directory.clone().unwrap_or_default();
-                filename.push(file.as_str());
-                // append to file
-                let mut f = File::options()
-                    .write(true)
-                    .append(true)
-                    .create(true)
-                    .open(&filename)
-                    .map_err(|e| Error::IoError(filename.clone(), e))?;
+            NCommand::Output { file, exprs, mode } => {
                 let mut termdag = TermDag::default();
+                let mut lines = vec![];
                 for expr in exprs {
                     let (t, value) = self.eval_expr(&expr, None, true)?;
                     let expr = self.extract(value, &mut termdag, &t).1;
-                    use std::io::Write;
-                    writeln!(f, "{}", termdag.to_string(&expr))
-                        .map_err(|e| Error::IoError(filename.clone(), e))?;
+                    lines.push(termdag.to_string(&expr));
                 }
 
-                log::info!("Output to '{filename:?}'.")
+                if mode == OutputMode::Stdout {
+                    for line in lines {
+                        self.print_msg(line);
+                    }
+                    log::info!("Output to stdout.")
+                } else {
+                    let mut filename = self.fact_directory.clone().unwrap_or_default();
+                    filename.push(file.as_str());
+                    let mut f = File::options()
+                        .write(true)
+                        .append(mode == OutputMode::Append)
+                        .truncate(mode == OutputMode::Truncate)
+                        .create(true)
+                        .open(&filename)
+                        .map_err(|e| Error::IoError(filename.clone(), e))?;
+                    for line in lines {
+                        use std::io::Write;
+                        writeln!(f, "{line}").map_err(|e| Error::IoError(filename.clone(), e))?;
+                    }
+                    log::info!("Output to '{filename:?}'.")
+                }
             }
         };
         Ok(())
@@ -1121,6 +1574,47 @@ impl EGraph {
         }
     }
 
+    /// Creates an e-graph by running the given commands, and remembers the
+    /// result as the baseline restored by [`EGraph::reset_to_prelude`].
+    pub fn with_prelude(commands: Vec<NormCommand>) -> Result<Self, Error> {
+        let mut egraph = Self::default();
+        egraph.desugar.type_info.typecheck_program(&commands)?;
+        for command in commands {
+            for action in command.command.top_level_actions() {
+                action.map_def_use(&mut |var, is_def| {
+                    if is_def {
+                        egraph.desugar.global_variables.insert(var);
+                    }
+                    var
+                });
+            }
+            egraph.run_command(command.command, true)?;
+        }
+        egraph.flush_msgs();
+        egraph.prelude = Some(Box::new(egraph.clone()));
+        Ok(egraph)
+    }
+
+    /// Restores the declarations and data captured by [`EGraph::with_prelude`].
+    /// Without a prelude, this resets to an empty e-graph.
+    pub fn reset_to_prelude(&mut self) {
+        let command_hooks = std::mem::take(&mut self.command_hooks);
+        match self.prelude.take() {
+            Some(prelude) => {
+                *self = (*prelude).clone();
+                self.prelude = Some(prelude);
+            }
+            None => *self = Self::default(),
+        }
+        self.command_hooks = command_hooks;
+    }
+
+    /// Registers a callback that runs after each desugared command is executed
+    /// by [`EGraph::run_program`], seeing the e-graph as the command left it.
+    pub fn on_command(&mut self, f: Box<dyn FnMut(&NormCommand, &EGraph)>) {
+        self.command_hooks.push(Rc::new(RefCell::new(f)));
+    }
+
     pub fn process_commands(
         &mut self,
         program: Vec<Command>,
@@ -1152,11 +1646,29 @@ impl EGraph {
         self.desugar.number_underscores = underscores;
     }
 
+    /// Whether top-level actions reuse the variables earlier commands bound to the same
+    /// constructor terms, instead of flattening them again. Off by default.
+    /// Bindings made after a `push` are forgotten at the matching `pop`.
+    pub fn set_share_subexpressions(&mut self, share: bool) {
+        self.desugar.session_memo = share.then(Default::default);
+    }
+
+    /// Sets what fresh names made while desugaring start with, `v` by default.
+    pub fn set_fresh_prefix_for_desugaring(&mut self, prefix: impl Into<String>) {
+        self.desugar.fresh_prefix = prefix.into();
+    }
+
     fn process_command(
         &mut self,
         command: Command,
         stop: CompilerPassStop,
     ) -> Result<Vec<NormCommand>, Error> {
+        // desugaring may already look for primitives
+        if !matches!(command, Command::Sort(..) | Command::Datatype { .. }) {
+            self.desugar.type_info.register_pending_primitives();
+        }
+        // kept to point type errors at the source
+        let source = command.clone();
         let program =
             self.desugar
                 .desugar_program(vec![command], self.test_proofs, self.seminaive)?;
@@ -1166,14 +1678,20 @@ impl EGraph {
 
         let type_info_before = self.desugar.type_info.clone();
 
-        self.desugar.type_info.typecheck_program(&program)?;
+        self.desugar
+            .type_info
+            .typecheck_program(&program)
+            .map_err(|err| err.locate(&source))?;
         if stop == CompilerPassStop::TypecheckDesugared {
             return Ok(program);
         }
 
         // reset type info
         self.desugar.type_info = type_info_before;
-        self.desugar.type_info.typecheck_program(&program)?;
+        self.desugar
+            .type_info
+            .typecheck_program(&program)
+            .map_err(|err| err.locate(&source))?;
         if stop == CompilerPassStop::TypecheckTermEncoding {
             return Ok(program);
         }
@@ -1188,7 +1706,14 @@ impl EGraph {
             // Important to process each command individually
             // because push and pop create new scopes
             for processed in self.process_command(command, CompilerPassStop::All)? {
-                self.run_command(processed.command, should_run)?;
+                if self.command_hooks.is_empty() {
+                    self.run_command(processed.command, should_run)?;
+                } else {
+                    self.run_command(processed.command.clone(), should_run)?;
+                    for hook in &self.command_hooks {
+                        (*hook.borrow_mut())(&processed, self);
+                    }
+                }
             }
         }
         log::logger().flush();
@@ -1215,6 +1740,67 @@ impl EGraph {
         self.run_program(parsed)
     }
 
+    /// Runs a single command built in Rust, as if it had been parsed from a program.
+    /// The helpers below build the common commands for embedders that construct
+    /// programs as [`ast`] values instead of strings.
+    pub fn run_ast_command(&mut self, command: Command) -> Result<Vec<String>, Error> {
+        self.run_program(vec![command])
+    }
+
+    pub fn add_datatype(
+        &mut self,
+        name: impl Into<Symbol>,
+        variants: Vec<Variant>,
+    ) -> Result<Vec<String>, Error> {
+        self.run_ast_command(Command::Datatype {
+            name: name.into(),
+            variants,
+            discriminators: false,
+            merge: None,
+        })
+    }
+
+    pub fn add_function(&mut self, decl: FunctionDecl) -> Result<Vec<String>, Error> {
+        self.run_ast_command(Command::Function(decl))
+    }
+
+    /// Adds a rule to `ruleset` (`""` for the default one). Unlike [`EGraph::add_rule`],
+    /// the rule is desugared first, so it may use any syntax a `rule` command can.
+    pub fn add_ast_rule(
+        &mut self,
+        ruleset: impl Into<Symbol>,
+        name: impl Into<Symbol>,
+        rule: ast::Rule,
+    ) -> Result<Vec<String>, Error> {
+        self.run_ast_command(Command::Rule {
+            ruleset: ruleset.into(),
+            name: name.into(),
+            rule,
+        })
+    }
+
+    pub fn add_rewrite(
+        &mut self,
+        ruleset: impl Into<Symbol>,
+        rewrite: Rewrite,
+    ) -> Result<Vec<String>, Error> {
+        self.run_ast_command(Command::Rewrite(ruleset.into(), rewrite))
+    }
+
+    pub fn add_actions(&mut self, actions: Vec<Action>) -> Result<Vec<String>, Error> {
+        self.run_ast_command(Command::Actions(actions))
+    }
+
+    /// Runs a schedule that hasn't been normalized yet; see [`EGraph::run_schedule`]
+    /// for one that has.
+    pub fn run_ast_schedule(&mut self, schedule: Schedule) -> Result<Vec<String>, Error> {
+        self.run_ast_command(Command::RunSchedule(schedule))
+    }
+
+    pub fn check_ast_facts(&mut self, facts: Vec<Fact>) -> Result<Vec<String>, Error> {
+        self.run_ast_command(Command::Check(facts))
+    }
+
     pub fn num_tuples(&self) -> usize {
         self.functions.values().map(|f| f.nodes.len()).sum()
     }
@@ -1227,6 +1813,73 @@ impl EGraph {
         self.desugar.type_info.add_arcsort(arcsort)
     }
 
+    /// Registers a primitive for the commands run after this.
+    /// Fails, changing nothing, if a function already has the primitive's name.
+    pub fn add_primitive(&mut self, prim: impl Into<Primitive>) -> Result<(), Error> {
+        let prim = prim.into();
+        if self.desugar.type_info.func_types.contains_key(&prim.name()) {
+            return Err(TypeError::FunctionAlreadyBound(prim.name()).into());
+        }
+        self.desugar.type_info.add_primitive(prim);
+        Ok(())
+    }
+
+    /// Adds a directory to search for files named by `include`, after the
+    /// including file's directory, the working directory and earlier paths.
+    pub fn add_include_path(&mut self, path: impl Into<PathBuf>) {
+        self.desugar.include_paths.push(path.into());
+    }
+
+    /// The sorts, primitives and function types known to the egraph.
+    pub fn type_info(&self) -> &TypeInfo {
+        &self.desugar.type_info
+    }
+
+    /// Returns the name of the sort of a global bound with `let`, if there is one.
+    pub fn global_sort(&self, name: Symbol) -> Option<Symbol> {
+        self.desugar
+            .type_info
+            .global_types
+            .get(&name)
+            .map(|sort| sort.name())
+    }
+
+    /// All the sorts declared so far, including the built-in ones, sorted by name.
+    pub fn sorts(&self) -> Vec<SortInfo> {
+        let mut sorts: Vec<SortInfo> = self
+            .desugar
+            .type_info
+            .sorts
+            .iter()
+            .map(|(name, sort)| SortInfo {
+                name: *name,
+                is_eq_sort: sort.is_eq_sort(),
+                is_container_sort: sort.is_container_sort(),
+            })
+            .collect();
+        sorts.sort_by_key(|sort| sort.name.as_str());
+        sorts
+    }
+
+    /// Statistics for the rules declared in `ruleset`, or `None` if there is no such ruleset.
+    /// A rule counts towards the ruleset it is declared in, whichever schedules run it.
+    pub fn ruleset_stats(&self, ruleset: Symbol) -> Option<RulesetStats> {
+        let mut rules: Vec<RuleStats> = self
+            .rulesets
+            .get(&ruleset)?
+            .iter()
+            .map(|(name, rule)| RuleStats {
+                name: *name,
+                matches: rule.matches,
+                times_banned: rule.times_banned,
+                search_time: rule.search_time,
+                apply_time: rule.apply_time,
+            })
+            .collect();
+        rules.sort_by_key(|rule| rule.name.as_str());
+        Some(RulesetStats { rules })
+    }
+
     /// Gets the last extract report and returns it, if the last command saved it.
     pub fn get_extract_report(&self) -> &Option<ExtractReport> {
         &self.extract_report
@@ -1244,6 +1897,11 @@ impl EGraph {
         serialized
     }
 
+    /// Takes the warnings produced so far, such as rules that can never match.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+
     pub(crate) fn print_msg(&mut self, msg: String) {
         self.msgs.push(msg);
     }
@@ -1262,7 +1920,7 @@ pub enum Error {
     NotFoundError(#[from] NotFoundError),
     #[error(transparent)]
     TypeError(#[from] TypeError),
-    #[error("Errors:\n{}", ListDisplay(.0, "\n"))]
+    #[error("Errors:\n{}", ListDisplay(ranked_type_errors(.0), "\n"))]
     TypeErrors(Vec<TypeError>),
     #[error("Check failed: \n{}", ListDisplay(.0, "\n"))]
     CheckError(Vec<NormFact>),
@@ -1270,12 +1928,22 @@ pub enum Error {
     PrimitiveError(Primitive, Vec<Value>),
     #[error("Illegal merge attempted for function {0}, {1:?} != {2:?}")]
     MergeError(Symbol, Value, Value),
+    #[error("Merge for function {0} depends on the order of old {1:?} and new {2:?}")]
+    MergeOrderError(Symbol, Value, Value),
+    #[error("Timed out after {0:?} running until the facts hold")]
+    CheckTimeout(Duration),
+    #[error("Rule {1} is already defined in ruleset '{0}'")]
+    DuplicateRuleName(Symbol, Symbol),
     #[error("Tried to pop too much")]
     Pop,
     #[error("Command should have failed.")]
     ExpectFail,
     #[error("IO error: {0}: {1}")]
     IoError(PathBuf, std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error("Panic: {0} (bindings: {:?})", ListDebug(.1, " "))]
+    UserPanic(String, Vec<Value>),
 }
 
 fn safe_shl(a: usize, b: usize) -> usize {
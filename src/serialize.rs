@@ -2,10 +2,13 @@ use ordered_float::NotNan;
 use std::collections::VecDeque;
 
 use crate::{
-    ast::{FunctionDecl, Id},
-    function::{table::hash_values, ValueVec},
-    util::HashMap,
-    EGraph, Value,
+    ast::{FunctionDecl, Id, DUMMY_SPAN},
+    function::{
+        table::{hash_values, Table},
+        ValueVec,
+    },
+    util::{HashMap, IndexMap},
+    EGraph, Error, Function, Symbol, TypeError, Value,
 };
 
 pub struct SerializeConfig {
@@ -15,6 +18,8 @@ pub struct SerializeConfig {
     pub max_calls_per_function: Option<usize>,
     // Whether to include temporary functions in the serialized graph
     pub include_temporary_functions: bool,
+    // Whether to include functions marked :unextractable, which extraction never picks
+    pub include_unextractable_functions: bool,
 }
 
 impl Default for SerializeConfig {
@@ -23,11 +28,50 @@ impl Default for SerializeConfig {
             max_functions: Some(40),
             max_calls_per_function: Some(40),
             include_temporary_functions: false,
+            include_unextractable_functions: true,
         }
     }
 }
 
+/// How the calls of a serialized function combine,
+/// which the nodes of an [`egraph_serialize::EGraph`] don't record.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct SerializedFunction {
+    pub name: String,
+    /// The `:merge` expression, if there is one
+    pub merge: Option<String>,
+    /// The `:default` expression, if there is one
+    pub default: Option<String>,
+    /// Whether the function returns an eq sort and unions its outputs,
+    /// like the constructors of a datatype without a `:merge`
+    pub is_datatype: bool,
+}
+
+/// A row of a function's table, as written by [`EGraph::table_json`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+struct TableRow {
+    inputs: Vec<String>,
+    output: String,
+}
+
 impl EGraph {
+    /// Whether serializing with this config would leave out some functions or calls
+    /// because of `max_functions` or `max_calls_per_function`.
+    pub fn serialization_truncated(&self, config: &SerializeConfig) -> bool {
+        let sizes: Vec<usize> = self
+            .functions
+            .values()
+            .filter(|f| self.should_serialize(f, config))
+            .map(|function| function.nodes.len())
+            .filter(|&size| size > 0)
+            .collect();
+        let too_many_functions = config.max_functions.map_or(false, |max| sizes.len() > max);
+        let too_many_calls = config
+            .max_calls_per_function
+            .map_or(false, |max| sizes.iter().any(|&size| size > max));
+        too_many_functions || too_many_calls
+    }
+
     /// Serialize the egraph into a format that can be read by the egraph-serialize crate.
     ///
     /// There are multiple different semantically valid ways to do this.
@@ -46,6 +90,10 @@ impl EGraph {
     /// - Eq sorts: Use the canonical ID of the e-class
     /// - Primitives: Use the node ID
     ///
+    /// Container sorts are emitted as a node named after the sort, with one child per element:
+    /// a node in the element's e-class for eq sorts, or the element's own node otherwise.
+    /// An e-class none of whose calls are included gets a placeholder node named after its sort.
+    ///
     /// This is to achieve the following properties:
     /// - Equivalent primitive values will show up once in the e-graph.
     /// - Functions which return primitive values will be added to the e-class of that value.
@@ -53,30 +101,25 @@ impl EGraph {
     /// - Edges in the visualization will be well distributed (used for animating changes in the visualization)
     ///   (Note that this will be changed in `<https://github.com/egraphs-good/egglog/pull/158>` so that edges point to exact nodes instead of looking up the e-class)
     pub fn serialize(&self, config: SerializeConfig) -> egraph_serialize::EGraph {
+        if self.serialization_truncated(&config) {
+            log::warn!("Serialized e-graph is truncated, increase the limits in SerializeConfig to include everything");
+        }
         // First collect a list of all the calls we want to serialize, into the function decl, the inputs, and the output, and if its an eq sort
-        let all_calls: Vec<(&FunctionDecl, &ValueVec, &Value, egraph_serialize::NodeId)> = self
+        // Functions hold `Rc`s, so only their declarations and tables are handed to other threads
+        let functions: Vec<(&FunctionDecl, &Table)> = self
             .functions
             .values()
-            .filter(|f| {
-                config.include_temporary_functions || !self.is_temp_name(f.decl.name.to_string())
-            })
-            .map(|function| {
-                function
-                    .nodes
-                    .vals
-                    .iter()
-                    .filter(|(i, _)| i.live())
-                    .take(config.max_calls_per_function.unwrap_or(usize::MAX))
-                    .map(|(input, output)| {
-                        (
-                            &function.decl,
-                            &input.data,
-                            &output.value,
-                            format!("{}-{}", function.decl.name, hash_values(&input.data)).into(),
-                        )
-                    })
-                    .collect::<Vec<_>>()
-            })
+            .filter(|f| self.should_serialize(f, &config))
+            .map(|function| (&function.decl, &function.nodes))
+            .collect();
+        let max_calls = config.max_calls_per_function.unwrap_or(usize::MAX);
+        #[cfg(feature = "parallel")]
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        #[cfg(not(feature = "parallel"))]
+        let threads = 1;
+        let calls_per_function = calls_per_function(&functions, max_calls, threads);
+        let all_calls: Vec<Call> = calls_per_function
+            .into_iter()
             // Filter out functions with no calls
             .filter(|f| !f.is_empty())
             .take(config.max_functions.unwrap_or(usize::MAX))
@@ -124,6 +167,23 @@ impl EGraph {
         egraph
     }
 
+    /// Describes the functions whose calls [`EGraph::serialize`] includes with this config.
+    // `is_empty` also counts stale rows, so compare the number of live rows
+    #[allow(clippy::len_zero)]
+    pub fn serialize_functions(&self, config: &SerializeConfig) -> Vec<SerializedFunction> {
+        self.functions
+            .values()
+            .filter(|f| self.should_serialize(f, config) && f.nodes.len() > 0)
+            .take(config.max_functions.unwrap_or(usize::MAX))
+            .map(|function| SerializedFunction {
+                name: function.decl.name.to_string(),
+                merge: function.decl.merge.as_ref().map(|e| e.to_string()),
+                default: function.decl.default.as_ref().map(|e| e.to_string()),
+                is_datatype: function.schema.output.is_eq_sort() && function.decl.merge.is_none(),
+            })
+            .collect()
+    }
+
     /// Serialize the value and return the eclass and node ID
     /// If this is a primitive value, we will add the node to the data, but if it is an eclass, we will not
     fn serialize_value(
@@ -149,14 +209,27 @@ impl EGraph {
                     let children: Vec<egraph_serialize::NodeId> = sort
                         .inner_values(value)
                         .into_iter()
-                        .filter_map(|(_, v)| self.serialize_value(egraph, node_ids, &v).1)
+                        .map(|(elem_sort, v)| {
+                            let (class_id, node_id) = self.serialize_value(egraph, node_ids, &v);
+                            // don't drop elements whose e-class has none of its calls included
+                            node_id.unwrap_or_else(|| {
+                                let node_id =
+                                    format!("{}-class-{}", elem_sort.name(), self.export_value(&v));
+                                placeholder_node(
+                                    egraph,
+                                    node_ids,
+                                    elem_sort.name(),
+                                    class_id,
+                                    node_id,
+                                )
+                            })
+                        })
                         .collect();
                     // If this is a container sort, use the name, otherwise use the value
                     let op: String = if sort.is_container_sort() {
-                        log::warn!("{} is a container sort", sort.name());
                         sort.name().to_string()
                     } else {
-                        sort.make_expr(self, *value).1.to_string()
+                        self.export_value(value)
                     };
                     egraph.nodes.insert(
                         node_id.clone(),
@@ -179,23 +252,229 @@ impl EGraph {
         (class_id, node_id)
     }
 
-    /// Returns true if the name is in the form v{digits}__
+    /// Serializes the egraph as in [`EGraph::serialize`] and returns it as JSON,
+    /// without going through a file.
+    pub fn to_serialized_json(&self, config: SerializeConfig) -> Result<String, Error> {
+        Ok(serde_json::to_string(&self.serialize(config))?)
+    }
+
+    /// Writes the first `max_rows` rows of a function as a JSON array of
+    /// `{"inputs": [...], "output": ...}` objects.
+    ///
+    /// Values are strings: the canonical e-class ID for eq sorts, and the value
+    /// as an expression otherwise, like the primitive nodes of [`EGraph::serialize`].
+    /// Unlike there, a function returning a primitive keeps its output in `output`
+    /// instead of pointing into the e-class of that value.
+    pub fn table_json(&self, name: Symbol, max_rows: usize) -> Result<String, Error> {
+        let function = self
+            .functions
+            .get(&name)
+            .ok_or(TypeError::Unbound(name, DUMMY_SPAN))?;
+        let rows: Vec<TableRow> = function
+            .nodes
+            .iter()
+            .take(max_rows)
+            .map(|(inputs, output)| TableRow {
+                inputs: inputs.iter().map(|v| self.export_value(v)).collect(),
+                output: self.export_value(&output.value),
+            })
+            .collect();
+        Ok(serde_json::to_string(&rows)?)
+    }
+
+    /// A value as a string: the canonical e-class ID for eq sorts, otherwise the value's expression
+    fn export_value(&self, value: &Value) -> String {
+        let sort = self.get_sort(value).unwrap();
+        if sort.is_eq_sort() {
+            let canonical: usize = self.unionfind.find(Id::from(value.bits as usize)).into();
+            canonical.to_string()
+        } else {
+            sort.make_expr(self, *value).1.to_string()
+        }
+    }
+
+    /// Renders the serialized egraph as Graphviz DOT.
+    ///
+    /// Each e-class is a cluster holding its calls, and each call has an edge to the
+    /// e-class of each of its inputs. Primitive values are leaf nodes outside any cluster,
+    /// labeled with the value. Nodes and clusters are numbered in the order [`EGraph::serialize`]
+    /// emits them, so the output is the same from run to run.
+    pub fn to_dot(&self, config: SerializeConfig) -> String {
+        let graph = self.serialize(config);
+        let is_call = |node: &egraph_serialize::Node| {
+            self.functions.contains_key(&Symbol::from(node.op.as_str()))
+        };
+        let node_index: HashMap<&egraph_serialize::NodeId, usize> = graph
+            .nodes
+            .keys()
+            .enumerate()
+            .map(|(i, id)| (id, i))
+            .collect();
+        let mut classes: IndexMap<&egraph_serialize::ClassId, Vec<usize>> = IndexMap::default();
+        for (id, node) in &graph.nodes {
+            classes
+                .entry(&node.eclass)
+                .or_default()
+                .push(node_index[id]);
+        }
+        let nodes: Vec<&egraph_serialize::Node> = graph.nodes.values().collect();
+        let is_cluster = |class: &[usize]| class.iter().any(|&i| is_call(nodes[i]));
+
+        let mut dot = String::from("digraph egraph {\n  compound=true\n");
+        for (i, (class, members)) in classes.iter().enumerate() {
+            if is_cluster(members) {
+                let typ = graph
+                    .class_data
+                    .get(*class)
+                    .and_then(|data| data.typ.as_deref());
+                dot.push_str(&format!(
+                    "  subgraph cluster_{i} {{\n    label=\"{}\"\n",
+                    dot_escape(typ.unwrap_or(""))
+                ));
+                for &n in members {
+                    dot.push_str(&format!(
+                        "    n{n} [label=\"{}\"]\n",
+                        dot_escape(&nodes[n].op)
+                    ));
+                }
+                dot.push_str("  }\n");
+            } else {
+                for &n in members {
+                    dot.push_str(&format!(
+                        "  n{n} [label=\"{}\", shape=plaintext]\n",
+                        dot_escape(&nodes[n].op)
+                    ));
+                }
+            }
+        }
+        for (n, node) in nodes.iter().enumerate() {
+            for child in &node.children {
+                let c = node_index[child];
+                let class = &nodes[c].eclass;
+                if is_cluster(&classes[class]) {
+                    dot.push_str(&format!(
+                        "  n{n} -> n{c} [lhead=cluster_{}]\n",
+                        classes.get_index_of(class).unwrap()
+                    ));
+                } else {
+                    dot.push_str(&format!("  n{n} -> n{c}\n"));
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn should_serialize(&self, function: &Function, config: &SerializeConfig) -> bool {
+        (config.include_temporary_functions || !self.is_temp_name(function.decl.name.to_string()))
+            && (config.include_unextractable_functions || !function.decl.unextractable)
+    }
+
+    /// Returns true if the name is in the form {prefix}{digits}__
     /// like v78___
     ///
     /// Checks for pattern created by Desugar.get_fresh
-    fn is_temp_name(&self, name: String) -> bool {
-        let number_underscores = self.desugar.number_underscores;
-        let res = name.starts_with('v')
-            && name.ends_with("_".repeat(number_underscores).as_str())
-            && name[1..name.len() - number_underscores]
-                .parse::<u32>()
-                .is_ok();
-        res
+    pub(crate) fn is_temp_name(&self, name: String) -> bool {
+        let underscores = "_".repeat(self.desugar.number_underscores);
+        name.strip_prefix(self.desugar.fresh_prefix.as_str())
+            .and_then(|rest| rest.strip_suffix(underscores.as_str()))
+            .map_or(false, |digits| digits.parse::<u32>().is_ok())
     }
 }
 
+/// A call to serialize: the function decl, the inputs, the output, and the node ID
+type Call<'a> = (
+    &'a FunctionDecl,
+    &'a ValueVec,
+    &'a Value,
+    egraph_serialize::NodeId,
+);
+
+/// The first `max_calls` live calls in a function's table
+fn function_calls<'a>(decl: &'a FunctionDecl, nodes: &'a Table, max_calls: usize) -> Vec<Call<'a>> {
+    nodes
+        .vals
+        .iter()
+        .filter(|(i, _)| i.live())
+        .take(max_calls)
+        .map(|(input, output)| {
+            (
+                decl,
+                &input.data,
+                &output.value,
+                format!("{}-{}", decl.name, hash_values(&input.data)).into(),
+            )
+        })
+        .collect()
+}
+
+/// The calls of each function, collected on up to `threads` threads.
+/// Each thread takes a contiguous chunk of the functions, and joining them in order
+/// keeps the order of the functions, so the result doesn't depend on the threads.
+fn calls_per_function<'a>(
+    functions: &[(&'a FunctionDecl, &'a Table)],
+    max_calls: usize,
+    threads: usize,
+) -> Vec<Vec<Call<'a>>> {
+    if threads <= 1 {
+        return functions
+            .iter()
+            .map(|(decl, nodes)| function_calls(decl, nodes, max_calls))
+            .collect();
+    }
+    let chunk_size = ((functions.len() + threads - 1) / threads).max(1);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = functions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(decl, nodes)| function_calls(decl, nodes, max_calls))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Escapes a label for a double-quoted DOT string
+fn dot_escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
 type NodeIDs = HashMap<egraph_serialize::ClassId, VecDeque<egraph_serialize::NodeId>>;
 
+/// Adds a node standing for an e-class that has no serialized calls,
+/// so that containers can still link to it.
+fn placeholder_node(
+    egraph: &mut egraph_serialize::EGraph,
+    node_ids: &mut NodeIDs,
+    sort: Symbol,
+    class_id: egraph_serialize::ClassId,
+    node_id: String,
+) -> egraph_serialize::NodeId {
+    let node_id: egraph_serialize::NodeId = node_id.into();
+    egraph.nodes.insert(
+        node_id.clone(),
+        egraph_serialize::Node {
+            op: sort.to_string(),
+            eclass: class_id.clone(),
+            cost: NotNan::new(0.0).unwrap(),
+            children: vec![],
+        },
+    );
+    node_ids
+        .entry(class_id)
+        .or_default()
+        .push_back(node_id.clone());
+    node_id
+}
+
 /// Returns the node ID for the given class ID, rotating the queue
 fn get_node_id(
     node_ids: &mut HashMap<egraph_serialize::ClassId, VecDeque<egraph_serialize::NodeId>>,
@@ -205,3 +484,43 @@ fn get_node_id(
     node_ids.rotate_left(1);
     Some(node_ids.front().unwrap().clone())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_per_function_matches_serial() {
+        let mut egraph = EGraph::default();
+        egraph
+            .parse_and_run_program(
+                "(datatype Math (Num i64) (Add Math Math))
+(function f (Math) i64)
+(function g (i64) i64)
+(relation r (i64))
+(Add (Num 1) (Add (Num 2) (Num 3)))
+(set (f (Num 1)) 4)
+(set (g 5) 6)
+(set (g 7) 8)
+(r 9)",
+            )
+            .unwrap();
+        let functions: Vec<(&FunctionDecl, &Table)> = egraph
+            .functions
+            .values()
+            .map(|function| (&function.decl, &function.nodes))
+            .collect();
+        let node_ids = |calls: Vec<Vec<Call>>| -> Vec<Vec<egraph_serialize::NodeId>> {
+            calls
+                .into_iter()
+                .map(|calls| calls.into_iter().map(|(_, _, _, id)| id).collect())
+                .collect()
+        };
+        let serial = node_ids(calls_per_function(&functions, usize::MAX, 1));
+        assert_eq!(serial.len(), functions.len());
+        for threads in [2, 3, functions.len() + 1] {
+            let parallel = node_ids(calls_per_function(&functions, usize::MAX, threads));
+            assert_eq!(parallel, serial, "{threads} threads");
+        }
+    }
+}
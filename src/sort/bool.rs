@@ -0,0 +1,84 @@
+use crate::{ast::Literal, ArcSort, PrimitiveLike};
+
+use super::*;
+
+#[derive(Debug)]
+pub struct BoolSort {
+    name: Symbol,
+}
+
+impl BoolSort {
+    pub fn new(name: Symbol) -> Self {
+        Self { name }
+    }
+}
+
+impl Sort for BoolSort {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn as_arc_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync + 'static> {
+        self
+    }
+
+    #[rustfmt::skip]
+    fn register_primitives(self: Arc<Self>, typeinfo: &mut TypeInfo) {
+        add_primitives!(typeinfo, "not" = |a: bool| -> bool { !a });
+        add_primitives!(typeinfo, "and" = |a: bool, b: bool| -> bool { a && b });
+        add_primitives!(typeinfo, "or" = |a: bool, b: bool| -> bool { a || b });
+        add_primitives!(typeinfo, "xor" = |a: bool, b: bool| -> bool { a ^ b });
+        typeinfo.add_primitive(IfPrimitive { bool: self });
+    }
+
+    fn make_expr(&self, _egraph: &EGraph, value: Value) -> (Cost, Expr) {
+        assert!(value.tag == self.name());
+        (1, Expr::Lit(DUMMY_SPAN, Literal::Bool(value.bits != 0)))
+    }
+}
+
+impl IntoSort for bool {
+    type Sort = BoolSort;
+    fn store(self, sort: &Self::Sort) -> Option<Value> {
+        Some(Value {
+            tag: sort.name,
+            bits: self as u64,
+        })
+    }
+}
+
+impl FromSort for bool {
+    type Sort = BoolSort;
+    fn load(_sort: &Self::Sort, value: &Value) -> Self {
+        value.bits != 0
+    }
+}
+
+/// `(if c a b)` is `a` when `c` is true and `b` otherwise,
+/// for `a` and `b` of any one sort.
+pub struct IfPrimitive {
+    bool: ArcSort,
+}
+
+impl PrimitiveLike for IfPrimitive {
+    fn name(&self) -> Symbol {
+        "if".into()
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [cond, a, b] if cond.name() == self.bool.name() && a.name() == b.name() => {
+                Some(a.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        Some(if values[0].bits != 0 {
+            values[1]
+        } else {
+            values[2]
+        })
+    }
+}
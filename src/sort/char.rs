@@ -0,0 +1,69 @@
+use crate::ast::Literal;
+
+use super::*;
+
+/// Single Unicode scalar values, stored as their code point
+#[derive(Debug)]
+pub struct CharSort {
+    name: Symbol,
+}
+
+impl CharSort {
+    pub fn new(name: Symbol) -> Self {
+        Self { name }
+    }
+}
+
+impl Sort for CharSort {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn as_arc_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync + 'static> {
+        self
+    }
+
+    #[rustfmt::skip]
+    fn register_primitives(self: Arc<Self>, typeinfo: &mut TypeInfo) {
+        type Opt<T=()> = Option<T>;
+
+        // indexes by Unicode scalar value, with no result past the end
+        add_primitives!(typeinfo, "string-ref" = |s: String, i: i64| -> Opt<char> {
+            usize::try_from(i).ok().and_then(|i| s.as_str().chars().nth(i))
+        });
+        add_primitives!(typeinfo, "char-to-string" = |c: char| -> String { c.to_string() });
+        add_primitives!(typeinfo, "char-code" = |c: char| -> i64 { c as i64 });
+        // the inverse of `char-code`, with no result for surrogates and out of range codes
+        add_primitives!(typeinfo, "code-to-char" = |i: i64| -> Opt<char> {
+            u32::try_from(i).ok().and_then(std::char::from_u32)
+        });
+    }
+
+    fn make_expr(&self, _egraph: &EGraph, value: Value) -> (Cost, Expr) {
+        assert!(value.tag == self.name());
+        (
+            1,
+            Expr::call(
+                "code-to-char",
+                [Expr::Lit(DUMMY_SPAN, Literal::Int(value.bits as _))],
+            ),
+        )
+    }
+}
+
+impl IntoSort for char {
+    type Sort = CharSort;
+    fn store(self, sort: &Self::Sort) -> Option<Value> {
+        Some(Value {
+            tag: sort.name,
+            bits: self as u64,
+        })
+    }
+}
+
+impl FromSort for char {
+    type Sort = CharSort;
+    fn load(_sort: &Self::Sort, value: &Value) -> Self {
+        std::char::from_u32(value.bits as u32).unwrap()
+    }
+}
@@ -33,6 +33,7 @@ impl Sort for F64Sort {
 
         add_primitives!(eg, "+" = |a: f64, b: f64| -> f64 { a + b });
         add_primitives!(eg, "-" = |a: f64, b: f64| -> f64 { a - b });
+        add_primitives!(eg, "-" = |a: f64| -> f64 { -a });
         add_primitives!(eg, "*" = |a: f64, b: f64| -> f64 { a * b });
         add_primitives!(eg, "/" = |a: f64, b: f64| -> Opt<f64> { (b != 0.0).then(|| a / b) });
         add_primitives!(eg, "%" = |a: f64, b: f64| -> Opt<f64> { (b != 0.0).then(|| a % b) });
@@ -46,10 +47,15 @@ impl Sort for F64Sort {
         add_primitives!(eg, "max" = |a: f64, b: f64| -> f64 { a.max(b) });
         add_primitives!(eg, "abs" = |a: f64| -> f64 { a.abs() });
 
+        add_primitives!(eg, "atan2" = |a: f64, b: f64| -> f64 { a.atan2(b) });
+        add_primitives!(eg, "pow" = |a: f64, b: f64| -> f64 { a.powf(b) });
+        // log of a in base b, only defined for positive a and positive b other than 1
+        add_primitives!(eg, "log" = |a: f64, b: f64| -> Opt<f64> { (a > 0.0 && b > 0.0 && b != 1.0).then(|| a.log(b)) });
+
         add_primitives!(eg, "to-f64" = |a: i64| -> f64 { a as f64 });
         add_primitives!(eg, "to-i64" = |a: f64| -> i64 { a as i64 });
         // Use debug instead of to_string so that decimal place is always printed
-        add_primitives!(eg, "to-string" = |a: f64| -> Symbol { format!("{:?}", a).into() });
+        add_primitives!(eg, "to-string" = |a: f64| -> String { format!("{:?}", a) });
 
     }
 
@@ -57,7 +63,10 @@ impl Sort for F64Sort {
         assert!(value.tag == self.name());
         (
             1,
-            Expr::Lit(Literal::F64(OrderedFloat(f64::from_bits(value.bits)))),
+            Expr::Lit(
+                DUMMY_SPAN,
+                Literal::F64(OrderedFloat(f64::from_bits(value.bits))),
+            ),
         )
     }
 }
@@ -36,9 +36,17 @@ impl Sort for I64Sort {
 
         add_primitives!(typeinfo, "+" = |a: i64, b: i64| -> i64 { a + b });
         add_primitives!(typeinfo, "-" = |a: i64, b: i64| -> i64 { a - b });
+        // unary minus, with no result for i64::MIN
+        add_primitives!(typeinfo, "-" = |a: i64| -> Opt<i64> { a.checked_neg() });
         add_primitives!(typeinfo, "*" = |a: i64, b: i64| -> i64 { a * b });
-        add_primitives!(typeinfo, "/" = |a: i64, b: i64| -> Opt<i64> { (b != 0).then(|| a / b) });
-        add_primitives!(typeinfo, "%" = |a: i64, b: i64| -> Opt<i64> { (b != 0).then(|| a % b) });
+        // i64::MIN / -1 overflows, so these are checked as well as partial on zero
+        add_primitives!(typeinfo, "/" = |a: i64, b: i64| -> Opt<i64> { a.checked_div(b) });
+        add_primitives!(typeinfo, "%" = |a: i64, b: i64| -> Opt<i64> { a.checked_rem(b) });
+
+        // like + - and *, but with no result on overflow instead of wrapping or panicking
+        add_primitives!(typeinfo, "checked-add" = |a: i64, b: i64| -> Opt<i64> { a.checked_add(b) });
+        add_primitives!(typeinfo, "checked-sub" = |a: i64, b: i64| -> Opt<i64> { a.checked_sub(b) });
+        add_primitives!(typeinfo, "checked-mul" = |a: i64, b: i64| -> Opt<i64> { a.checked_mul(b) });
 
         add_primitives!(typeinfo, "&" = |a: i64, b: i64| -> i64 { a & b });
         add_primitives!(typeinfo, "|" = |a: i64, b: i64| -> i64 { a | b });
@@ -56,14 +64,15 @@ impl Sort for I64Sort {
 
         add_primitives!(typeinfo, "min" = |a: i64, b: i64| -> i64 { a.min(b) });
         add_primitives!(typeinfo, "max" = |a: i64, b: i64| -> i64 { a.max(b) });
+        add_primitives!(typeinfo, "clamp" = |a: i64, lo: i64, hi: i64| -> Opt<i64> { (lo <= hi).then(|| a.max(lo).min(hi)) });
 
-        add_primitives!(typeinfo, "to-string" = |a: i64| -> Symbol { a.to_string().into() });
+        add_primitives!(typeinfo, "to-string" = |a: i64| -> String { a.to_string() });
 
     }
 
     fn make_expr(&self, _egraph: &EGraph, value: Value) -> (Cost, Expr) {
         assert!(value.tag == self.name());
-        (1, Expr::Lit(Literal::Int(value.bits as _)))
+        (1, Expr::Lit(DUMMY_SPAN, Literal::Int(value.bits as _)))
     }
 }
 
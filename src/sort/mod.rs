@@ -13,6 +13,10 @@ mod i64;
 pub use self::i64::*;
 mod f64;
 pub use self::f64::*;
+mod bool;
+pub use self::bool::*;
+mod char;
+pub use self::char::*;
 mod map;
 pub use map::*;
 mod set;
@@ -51,6 +51,9 @@ impl Sort for RationalSort {
         add_primitives!(eg, "round" = |a: R| -> R { a.round() });
         add_primitives!(eg, "rational" = |a: i64, b: i64| -> R { R::new(a, b) });
         add_primitives!(eg, "to-f64" = |a: R| -> f64 { a.to_f64().unwrap() });
+        add_primitives!(eg, "to-string" = |a: R| -> String { a.to_string() });
+        // the inverse of `to-string`, with no result for strings like "oops" or "1/0"
+        add_primitives!(eg, "string-to-rational" = |a: String| -> Opt<R> { a.as_str().parse().ok() });
 
         add_primitives!(eg, "pow" = |a: R, b: R| -> Option<R> {
             if a.is_zero() {
@@ -116,8 +119,8 @@ impl Sort for RationalSort {
             Expr::call(
                 "rational",
                 vec![
-                    Expr::Lit(Literal::Int(numer)),
-                    Expr::Lit(Literal::Int(denom)),
+                    Expr::Lit(DUMMY_SPAN, Literal::Int(numer)),
+                    Expr::Lit(DUMMY_SPAN, Literal::Int(denom)),
                 ],
             ),
         )
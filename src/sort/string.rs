@@ -1,17 +1,28 @@
-use std::num::NonZeroU32;
+use std::sync::Mutex;
 
 use crate::ast::Literal;
 
 use super::*;
 
+/// Strings are interned in a table owned by the sort rather than the global
+/// symbol table, so the ones built while running are freed with the e-graph.
 #[derive(Debug)]
 pub struct StringSort {
     name: Symbol,
+    strings: Mutex<IndexSet<String>>,
 }
 
 impl StringSort {
     pub fn new(name: Symbol) -> Self {
-        Self { name }
+        Self {
+            name,
+            strings: Default::default(),
+        }
+    }
+
+    /// The number of distinct strings this sort has stored.
+    pub fn num_strings(&self) -> usize {
+        self.strings.lock().unwrap().len()
     }
 }
 
@@ -26,34 +37,161 @@ impl Sort for StringSort {
 
     fn make_expr(&self, _egraph: &EGraph, value: Value) -> (Cost, Expr) {
         assert!(value.tag == self.name);
-        let sym = Symbol::from(NonZeroU32::new(value.bits as _).unwrap());
-        (1, Expr::Lit(Literal::String(sym)))
+        let string = String::load(self, &value);
+        (1, Expr::Lit(DUMMY_SPAN, Literal::String(string.into())))
     }
 
     fn register_primitives(self: Arc<Self>, typeinfo: &mut TypeInfo) {
         typeinfo.add_primitive(Add {
             name: "+".into(),
+            string: self.clone(),
+        });
+        typeinfo.add_primitive(Pad {
+            name: "string-pad-left".into(),
+            string: self.clone(),
+            i64: typeinfo.get_sort(),
+            left: true,
+        });
+        typeinfo.add_primitive(Pad {
+            name: "string-pad-right".into(),
+            string: self.clone(),
+            i64: typeinfo.get_sort(),
+            left: false,
+        });
+        typeinfo.add_primitive(Substr {
+            name: "substr".into(),
+            string: self.clone(),
+            i64: typeinfo.get_sort(),
+        });
+        typeinfo.add_primitive(Repeat {
+            name: "string-repeat".into(),
+            string: self.clone(),
+            i64: typeinfo.get_sort(),
+            max_len: MAX_REPEAT_LEN,
+        });
+        typeinfo.add_primitive(ToLowercase {
+            name: "to-lowercase".into(),
+            string: self.clone(),
+        });
+        typeinfo.add_primitive(ToUppercase {
+            name: "to-uppercase".into(),
+            string: self.clone(),
+        });
+        typeinfo.add_primitive(Join {
+            name: "join".into(),
+            string: self.clone(),
+        });
+        typeinfo.add_primitive(Format {
+            name: "format".into(),
             string: self,
         });
+
+        // predicates return a bool, so facts compare them to `true` or `false`
+        type Opt<T = ()> = Option<T>;
+        add_primitives!(
+            typeinfo,
+            "contains" = |a: String, b: String| -> bool { a.as_str().contains(b.as_str()) }
+        );
+        // counts chars rather than bytes, with no result when the needle isn't there
+        add_primitives!(
+            typeinfo,
+            "string-index" = |a: String, b: String| -> Opt<i64> {
+                a.as_str()
+                    .find(b.as_str())
+                    .map(|byte| a[..byte].chars().count() as i64)
+            }
+        );
+        add_primitives!(
+            typeinfo,
+            "starts-with" = |a: String, b: String| -> bool { a.as_str().starts_with(b.as_str()) }
+        );
+        add_primitives!(
+            typeinfo,
+            "ends-with" = |a: String, b: String| -> bool { a.as_str().ends_with(b.as_str()) }
+        );
+
+        // lexicographic ordering, useful as a merge function
+        add_primitives!(
+            typeinfo,
+            "min-string" = |a: String, b: String| -> String {
+                if a.as_str() <= b.as_str() {
+                    a
+                } else {
+                    b
+                }
+            }
+        );
+        add_primitives!(
+            typeinfo,
+            "max-string" = |a: String, b: String| -> String {
+                if a.as_str() >= b.as_str() {
+                    a
+                } else {
+                    b
+                }
+            }
+        );
+
+        add_primitives!(
+            typeinfo,
+            "trim" = |a: String| -> String { a.as_str().trim().into() }
+        );
+        add_primitives!(
+            typeinfo,
+            "trim-start" = |a: String| -> String { a.as_str().trim_start().into() }
+        );
+        add_primitives!(
+            typeinfo,
+            "trim-end" = |a: String| -> String { a.as_str().trim_end().into() }
+        );
+
+        // replaces every match of a regex, where the replacement can refer to groups like $1.
+        // invalid patterns have no result instead of failing the whole run
+        add_primitives!(
+            typeinfo,
+            "replace-regex" = |s: String, pattern: String, replacement: String| -> Opt<String> {
+                regex::Regex::new(pattern.as_str()).ok().map(|re| {
+                    re.replace_all(s.as_str(), replacement.as_str())
+                        .into_owned()
+                })
+            }
+        );
+
+        // whether the regex matches anywhere in the string, with no result for invalid patterns
+        add_primitives!(
+            typeinfo,
+            "regex-match" = |s: String, pattern: String| -> Opt<bool> {
+                regex::Regex::new(pattern.as_str())
+                    .ok()
+                    .map(|re| re.is_match(s.as_str()))
+            }
+        );
+
+        // the inverse of `to-string` on i64, with no result for strings that don't parse
+        add_primitives!(
+            typeinfo,
+            "string-to-i64" = |a: String| -> Opt<i64> { a.as_str().parse().ok() }
+        );
     }
 }
 
-// TODO could use a local symbol table
-
-impl IntoSort for Symbol {
+impl IntoSort for String {
     type Sort = StringSort;
     fn store(self, sort: &Self::Sort) -> Option<Value> {
+        let mut strings = sort.strings.lock().unwrap();
+        let (i, _) = strings.insert_full(self);
         Some(Value {
             tag: sort.name,
-            bits: NonZeroU32::from(self).get() as _,
+            bits: i as u64,
         })
     }
 }
 
-impl FromSort for Symbol {
+impl FromSort for String {
     type Sort = StringSort;
-    fn load(_sort: &Self::Sort, value: &Value) -> Self {
-        NonZeroU32::new(value.bits as u32).unwrap().into()
+    fn load(sort: &Self::Sort, value: &Value) -> Self {
+        let strings = sort.strings.lock().unwrap();
+        strings.get_index(value.bits as usize).unwrap().clone()
     }
 }
 
@@ -78,10 +216,275 @@ impl PrimitiveLike for Add {
     fn apply(&self, values: &[Value]) -> Option<Value> {
         let mut res_string: String = "".to_owned();
         for value in values {
-            let sym = Symbol::load(&self.string, value);
-            res_string.push_str(sym.as_str());
+            res_string.push_str(&String::load(&self.string, value));
+        }
+        res_string.store(&self.string)
+    }
+}
+
+/// Concatenates all but the first argument, with the first between each of them.
+struct Join {
+    name: Symbol,
+    string: Arc<StringSort>,
+}
+
+impl PrimitiveLike for Join {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        if !types.is_empty() && types.iter().all(|t| t.name() == self.string.name) {
+            Some(self.string.clone())
+        } else {
+            None
         }
-        let res_symbol: Symbol = res_string.into();
-        Some(Value::from(res_symbol))
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        let sep = String::load(&self.string, &values[0]);
+        let parts: Vec<String> = values[1..]
+            .iter()
+            .map(|value| String::load(&self.string, value))
+            .collect();
+        parts.join(&sep).store(&self.string)
+    }
+}
+
+/// Pads a string with repetitions of another string until it is the given
+/// number of characters wide. Longer strings are returned as is.
+struct Pad {
+    name: Symbol,
+    string: Arc<StringSort>,
+    i64: Arc<I64Sort>,
+    left: bool,
+}
+
+impl PrimitiveLike for Pad {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [input, width, pad]
+                if (input.name(), width.name(), pad.name())
+                    == (self.string.name, self.i64.name(), self.string.name) =>
+            {
+                Some(self.string.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        let input = String::load(&self.string, &values[0]);
+        let width = i64::load(&self.i64, &values[1]);
+        let pad = String::load(&self.string, &values[2]);
+
+        let len = input.as_str().chars().count();
+        let missing = usize::try_from(width).unwrap_or(0).saturating_sub(len);
+        if missing > 0 && pad.as_str().is_empty() {
+            return None;
+        }
+        let padding: String = pad.as_str().chars().cycle().take(missing).collect();
+        let res_string = if self.left {
+            padding + input.as_str()
+        } else {
+            input.as_str().to_owned() + &padding
+        };
+        res_string.store(&self.string)
+    }
+}
+
+/// Takes `len` characters starting at character `start`.
+/// Both are clamped to the string, so this never fails.
+struct Substr {
+    name: Symbol,
+    string: Arc<StringSort>,
+    i64: Arc<I64Sort>,
+}
+
+impl PrimitiveLike for Substr {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [input, start, len]
+                if (input.name(), start.name(), len.name())
+                    == (self.string.name, self.i64.name(), self.i64.name()) =>
+            {
+                Some(self.string.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        let input = String::load(&self.string, &values[0]);
+        let start = i64::load(&self.i64, &values[1]);
+        let len = i64::load(&self.i64, &values[2]);
+
+        let start = usize::try_from(start).unwrap_or(0);
+        let len = usize::try_from(len).unwrap_or(0);
+        let res_string: String = input.as_str().chars().skip(start).take(len).collect();
+        res_string.store(&self.string)
+    }
+}
+
+/// The longest string, in bytes, that `string-repeat` will build.
+pub const MAX_REPEAT_LEN: usize = 1 << 20;
+
+/// Repeats a string a number of times. There is no result for negative counts
+/// or when the result would be longer than `max_len` bytes.
+struct Repeat {
+    name: Symbol,
+    string: Arc<StringSort>,
+    i64: Arc<I64Sort>,
+    max_len: usize,
+}
+
+impl PrimitiveLike for Repeat {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [input, count]
+                if (input.name(), count.name()) == (self.string.name, self.i64.name()) =>
+            {
+                Some(self.string.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        let input = String::load(&self.string, &values[0]);
+        let count = usize::try_from(i64::load(&self.i64, &values[1])).ok()?;
+        if input.as_str().len().checked_mul(count)? > self.max_len {
+            return None;
+        }
+        input.as_str().repeat(count).store(&self.string)
+    }
+}
+
+// Both case conversions use the full Unicode mapping, so the length can change
+struct ToLowercase {
+    name: Symbol,
+    string: Arc<StringSort>,
+}
+
+impl PrimitiveLike for ToLowercase {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [input] if input.name() == self.string.name => Some(self.string.clone()),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        let input = String::load(&self.string, &values[0]);
+        input.as_str().to_lowercase().store(&self.string)
+    }
+}
+
+struct ToUppercase {
+    name: Symbol,
+    string: Arc<StringSort>,
+}
+
+impl PrimitiveLike for ToUppercase {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [input] if input.name() == self.string.name => Some(self.string.clone()),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        let input = String::load(&self.string, &values[0]);
+        input.as_str().to_uppercase().store(&self.string)
+    }
+}
+
+/// Fills each `{}` in a template with the next argument, taking any number of them.
+/// There is no result unless the placeholders and arguments match up one to one.
+struct Format {
+    name: Symbol,
+    string: Arc<StringSort>,
+}
+
+impl PrimitiveLike for Format {
+    fn name(&self) -> Symbol {
+        self.name
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        if !types.is_empty() && types.iter().all(|t| t.name() == self.string.name) {
+            Some(self.string.clone())
+        } else {
+            None
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        let template = String::load(&self.string, &values[0]);
+        let mut pieces = template.as_str().split("{}");
+        let mut args = values[1..].iter().map(|v| String::load(&self.string, v));
+        let mut res = pieces.next().unwrap().to_string();
+        for piece in pieces {
+            res.push_str(args.next()?.as_str());
+            res.push_str(piece);
+        }
+        if args.next().is_some() {
+            return None;
+        }
+        res.store(&self.string)
+    }
+}
+
+/// Splits a string on a separator, like [`str::split`], into a vec of strings.
+/// It is registered by each `(Vec String)` sort, since only those can hold the parts.
+pub(crate) struct Split {
+    pub(crate) string: Arc<StringSort>,
+    pub(crate) vec: Arc<VecSort>,
+}
+
+impl PrimitiveLike for Split {
+    fn name(&self) -> Symbol {
+        "string-split".into()
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [haystack, separator]
+                if haystack.name() == self.string.name && separator.name() == self.string.name =>
+            {
+                Some(self.vec.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        let haystack = String::load(&self.string, &values[0]);
+        let separator = String::load(&self.string, &values[1]);
+        let parts: Option<Vec<Value>> = haystack
+            .split(separator.as_str())
+            .map(|part| part.to_owned().store(&self.string))
+            .collect();
+        parts?.store(&self.vec)
     }
 }
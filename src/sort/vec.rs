@@ -27,6 +27,7 @@ impl VecSort {
             "vec-contains".into(),
             "vec-length".into(),
             "vec-get".into(),
+            "string-split".into(),
         ]
     }
 
@@ -35,7 +36,7 @@ impl VecSort {
         name: Symbol,
         args: &[Expr],
     ) -> Result<ArcSort, TypeError> {
-        if let [Expr::Var(e)] = args {
+        if let [Expr::Var(_, e)] = args {
             let e = typeinfo.sorts.get(e).ok_or(TypeError::UndefinedSort(*e))?;
 
             if e.is_eq_container_sort() {
@@ -138,9 +139,12 @@ impl Sort for VecSort {
         });
         typeinfo.add_primitive(Get {
             name: "vec-get".into(),
-            vec: self,
+            vec: self.clone(),
             i64: typeinfo.get_sort(),
-        })
+        });
+        if let Ok(string) = Arc::downcast::<StringSort>(self.element.clone().as_arc_any()) {
+            typeinfo.add_primitive(Split { string, vec: self });
+        }
     }
 
     fn make_expr(&self, egraph: &EGraph, value: Value) -> (Cost, Expr) {
@@ -1,5 +1,5 @@
 use crate::{
-    ast::{Expr, Literal},
+    ast::{Expr, Literal, DUMMY_SPAN},
     util::{HashMap, HashSet},
     Symbol,
 };
@@ -120,9 +120,9 @@ impl TermDag {
     /// share subterms.
     pub fn expr_to_term(&mut self, expr: &Expr) -> Term {
         let res = match expr {
-            Expr::Lit(lit) => Term::Lit(lit.clone()),
-            Expr::Var(v) => Term::Var(*v),
-            Expr::Call(op, args) => {
+            Expr::Lit(_, lit) => Term::Lit(lit.clone()),
+            Expr::Var(_, v) => Term::Var(*v),
+            Expr::Call(_, op, args) => {
                 let args = args
                     .iter()
                     .map(|a| {
@@ -142,8 +142,8 @@ impl TermDag {
     /// Panics if the term contains subterms that are not in the DAG.
     pub fn term_to_expr(&self, term: &Term) -> Expr {
         match term {
-            Term::Lit(lit) => Expr::Lit(lit.clone()),
-            Term::Var(v) => Expr::Var(*v),
+            Term::Lit(lit) => Expr::Lit(DUMMY_SPAN, lit.clone()),
+            Term::Var(v) => Expr::Var(DUMMY_SPAN, *v),
             Term::App(op, args) => {
                 let args = args
                     .iter()
@@ -152,7 +152,7 @@ impl TermDag {
                         self.term_to_expr(&term)
                     })
                     .collect();
-                Expr::Call(*op, args)
+                Expr::Call(DUMMY_SPAN, *op, args)
             }
         }
     }
@@ -242,7 +242,7 @@ mod tests {
         let (td, t) = parse_term(s);
         match_term_app!(t; {
             ("f", [_, x, _, _]) =>
-                assert_eq!(td.term_to_expr(&td.get(*x)), ast::Expr::Var(Symbol::new("x")))
+                assert_eq!(td.term_to_expr(&td.get(*x)), ast::Expr::Var(DUMMY_SPAN, Symbol::new("x")))
         })
     }
 
@@ -9,6 +9,10 @@ pub struct Context<'a> {
     errors: Vec<TypeError>,
     unionfind: UnionFind,
     nodes: HashMap<ENode, Id>,
+    // pairs of distinct literals that the query forces to be equal
+    conflicting_literals: Vec<(Literal, Literal)>,
+    // calls that must not have a row, checked once the rest of the query matched
+    absent: Vec<(Symbol, Vec<Id>)>,
 }
 
 #[derive(Hash, Eq, PartialEq, Clone)]
@@ -52,6 +56,9 @@ impl<T: std::fmt::Display> std::fmt::Display for Atom<T> {
 pub struct Query {
     pub atoms: Vec<Atom<Symbol>>,
     pub filters: Vec<Atom<Primitive>>,
+    /// Rows that must not exist for the query to match.
+    /// The atoms have no output column.
+    pub absent: Vec<Atom<Symbol>>,
 }
 
 impl std::fmt::Display for Query {
@@ -70,6 +77,9 @@ impl std::fmt::Display for Query {
                 )?;
             }
         }
+        for atom in &self.absent {
+            writeln!(f, "absent {atom}")?;
+        }
         Ok(())
     }
 }
@@ -117,9 +127,17 @@ impl<'a> Context<'a> {
             errors: Vec::default(),
             unionfind: UnionFind::default(),
             nodes: HashMap::default(),
+            conflicting_literals: vec![],
+            absent: vec![],
         }
     }
 
+    /// Literals the last query constrained to be equal to a different literal.
+    /// If there are any, the query can never match.
+    pub fn conflicting_literals(&self) -> &[(Literal, Literal)] {
+        &self.conflicting_literals
+    }
+
     fn add_node(&mut self, node: ENode) -> Id {
         let entry = self.nodes.entry(node);
         *entry.or_insert_with(|| self.unionfind.make_set())
@@ -144,12 +162,17 @@ impl<'a> Context<'a> {
         // Do literals first
         for (node, &id) in &self.nodes {
             match node {
-                ENode::Literal(lit) => {
-                    let old = leaves.insert(id, Expr::Lit(lit.clone()));
-                    if let Some(Expr::Lit(old_lit)) = old {
-                        panic!("Duplicate literal: {:?} {:?}", old_lit, lit);
+                ENode::Literal(lit) => match leaves.entry(id) {
+                    Entry::Occupied(existing) => {
+                        if let Expr::Lit(_, old_lit) = existing.get() {
+                            self.conflicting_literals
+                                .push((old_lit.clone(), lit.clone()));
+                        }
                     }
-                }
+                    Entry::Vacant(v) => {
+                        v.insert(Expr::Lit(DUMMY_SPAN, lit.clone()));
+                    }
+                },
                 _ => continue,
             }
         }
@@ -163,7 +186,7 @@ impl<'a> Context<'a> {
                                 canon.insert(*var, existing.get().clone());
                             }
                             Entry::Vacant(v) => {
-                                v.insert(Expr::Var(*var));
+                                v.insert(Expr::Var(DUMMY_SPAN, *var));
                             }
                         }
                     }
@@ -181,7 +204,7 @@ impl<'a> Context<'a> {
                         canon.insert(*var, existing.get().clone());
                     }
                     Entry::Vacant(v) => {
-                        v.insert(Expr::Var(*var));
+                        v.insert(Expr::Var(DUMMY_SPAN, *var));
                     }
                 },
                 _ => continue,
@@ -197,14 +220,14 @@ impl<'a> Context<'a> {
         let get_leaf = |id: &Id| -> AtomTerm {
             let mk = || AtomTerm::Var(Symbol::from(format!("?__{}", id)));
             match leaves.get(id) {
-                Some(Expr::Var(v)) => {
+                Some(Expr::Var(_, v)) => {
                     if let Some((_ty, _value, _ts)) = self.egraph.global_bindings.get(v) {
                         AtomTerm::Global(*v)
                     } else {
                         AtomTerm::Var(*v)
                     }
                 }
-                Some(Expr::Lit(l)) => AtomTerm::Value(self.egraph.eval_lit(l)),
+                Some(Expr::Lit(_, l)) => AtomTerm::Value(self.egraph.eval_lit(l)),
                 _ => mk(),
             }
         };
@@ -244,6 +267,20 @@ impl<'a> Context<'a> {
             }
         }
 
+        // distinct literals in the same e-class can never be equal,
+        // so add a filter that always fails
+        for (old_lit, lit) in &self.conflicting_literals {
+            let old_value = AtomTerm::Value(self.egraph.eval_lit(old_lit));
+            query.filters.push(Atom {
+                head: Primitive(Arc::new(ValueEq {})),
+                args: vec![
+                    old_value.clone(),
+                    AtomTerm::Value(self.egraph.eval_lit(lit)),
+                    old_value,
+                ],
+            });
+        }
+
         // filter for global variables
         for node in &self.nodes {
             if let ENode::Var(var) = node.0 {
@@ -260,6 +297,29 @@ impl<'a> Context<'a> {
             }
         }
 
+        // absent rows can only mention variables bound by the rest of the query
+        let mut bound = HashSet::<Symbol>::default();
+        for atom in &query.atoms {
+            bound.extend(atom.vars());
+        }
+        for filter in &query.filters {
+            bound.extend(filter.vars());
+        }
+        for (f, ids) in &self.absent {
+            let args: Vec<AtomTerm> = ids
+                .iter()
+                .map(|id| get_leaf(&self.unionfind.find(*id)))
+                .collect();
+            for arg in &args {
+                if let AtomTerm::Var(v) = arg {
+                    if !bound.contains(v) {
+                        self.errors.push(TypeError::Unbound(*v, DUMMY_SPAN));
+                    }
+                }
+            }
+            query.absent.push(Atom { head: *f, args });
+        }
+
         if self.errors.is_empty() {
             Ok((query, res_actions))
         } else {
@@ -304,7 +364,7 @@ impl<'a> Context<'a> {
                         }
                         // This is a variable the we couldn't infer the type of,
                         // so we'll try again later when we can check its type
-                        (Expr::Var(v), None)
+                        (Expr::Var(_, v), None)
                             if !self.types.contains_key(v)
                                 && !self.egraph.global_bindings.contains_key(v) =>
                         {
@@ -336,12 +396,35 @@ impl<'a> Context<'a> {
             Fact::Fact(e) => {
                 self.check_query_expr(e, self.unit.clone());
             }
+            Fact::Absent(e) => {
+                let Expr::Call(_, f, args) = e else {
+                    panic!("Expected a call in absent, got {e}");
+                };
+                let Some(func) = self.egraph.functions.get(f) else {
+                    self.errors.push(TypeError::UnboundFunction(*f));
+                    return;
+                };
+                if func.schema.input.len() != args.len() {
+                    self.errors.push(TypeError::Arity {
+                        expr: e.clone(),
+                        expected: func.schema.input.len(),
+                    });
+                    return;
+                }
+                let input = func.schema.input.clone();
+                let ids = args
+                    .iter()
+                    .zip(input)
+                    .map(|(arg, ty)| self.check_query_expr(arg, ty))
+                    .collect();
+                self.absent.push((*f, ids));
+            }
         }
     }
 
     fn check_query_expr(&mut self, expr: &Expr, expected: ArcSort) -> Id {
         match expr {
-            Expr::Var(sym) => {
+            Expr::Var(_, sym) => {
                 match self.types.entry(*sym) {
                     IEntry::Occupied(ty) => {
                         // TODO name comparison??
@@ -380,7 +463,7 @@ impl<'a> Context<'a> {
 
     fn infer_query_expr(&mut self, expr: &Expr) -> (Id, Option<ArcSort>) {
         match expr {
-            Expr::Var(sym) => {
+            Expr::Var(span, sym) => {
                 if self.egraph.functions.contains_key(sym) {
                     return self.infer_query_expr(&Expr::call(*sym, []));
                 }
@@ -390,16 +473,16 @@ impl<'a> Context<'a> {
                 } else if let Some(ty) = self.egraph.global_bindings.get(sym) {
                     Some(ty.0.clone())
                 } else {
-                    self.errors.push(TypeError::Unbound(*sym));
+                    self.errors.push(TypeError::Unbound(*sym, *span));
                     None
                 };
                 (self.add_node(ENode::Var(*sym)), ty)
             }
-            Expr::Lit(lit) => {
+            Expr::Lit(_, lit) => {
                 let t = self.egraph.desugar.type_info.infer_literal(lit);
                 (self.add_node(ENode::Literal(lit.clone())), Some(t))
             }
-            Expr::Call(sym, args) => {
+            Expr::Call(span, sym, args) => {
                 if let Some(f) = self.egraph.functions.get(sym) {
                     if f.schema.input.len() != args.len() {
                         self.errors.push(TypeError::Arity {
@@ -430,12 +513,13 @@ impl<'a> Context<'a> {
                         self.errors.push(TypeError::NoMatchingPrimitive {
                             op: *sym,
                             inputs: arg_tys.iter().map(|t| t.name()).collect(),
+                            span: *span,
                         });
                     }
 
                     (self.unionfind.make_set(), None)
                 } else {
-                    self.errors.push(TypeError::Unbound(*sym));
+                    self.errors.push(TypeError::Unbound(*sym, *span));
                     (self.unionfind.make_set(), None)
                 }
             }
@@ -462,7 +546,7 @@ impl<'a> ActionChecker<'a> {
                 Ok(())
             }
             Action::Set(f, args, val) => {
-                let fake_call = Expr::Call(*f, args.clone());
+                let fake_call = Expr::Call(DUMMY_SPAN, *f, args.clone());
                 let (_, ty) = self.infer_expr(&fake_call)?;
                 let fake_instr = self.instructions.pop().unwrap();
                 assert!(matches!(fake_instr, Instruction::CallFunction(..)));
@@ -477,13 +561,21 @@ impl<'a> ActionChecker<'a> {
                 Ok(())
             }
             Action::Delete(f, args) => {
-                let fake_call = Expr::Call(*f, args.clone());
+                let fake_call = Expr::Call(DUMMY_SPAN, *f, args.clone());
                 let (_, _ty) = self.infer_expr(&fake_call)?;
                 let fake_instr = self.instructions.pop().unwrap();
                 assert!(matches!(fake_instr, Instruction::CallFunction(..)));
                 self.instructions.push(Instruction::DeleteRow(*f));
                 Ok(())
             }
+            Action::Subsume(f, args) => {
+                let fake_call = Expr::Call(DUMMY_SPAN, *f, args.clone());
+                let (_, _ty) = self.infer_expr(&fake_call)?;
+                let fake_instr = self.instructions.pop().unwrap();
+                assert!(matches!(fake_instr, Instruction::CallFunction(..)));
+                self.instructions.push(Instruction::SubsumeRow(*f));
+                Ok(())
+            }
             Action::Union(a, b) => {
                 let (_, ty) = self.infer_expr(a)?;
                 if !ty.is_eq_sort() {
@@ -493,6 +585,14 @@ impl<'a> ActionChecker<'a> {
                 self.instructions.push(Instruction::Union(2));
                 Ok(())
             }
+            Action::Pin(e) => {
+                let (_, ty) = self.infer_expr(e)?;
+                if !ty.is_eq_sort() {
+                    return Err(TypeError::PinPrimitive(ty));
+                }
+                self.instructions.push(Instruction::Pin);
+                Ok(())
+            }
             Action::Panic(msg) => {
                 self.instructions.push(Instruction::Panic(msg.clone()));
                 Ok(())
@@ -517,7 +617,7 @@ impl<'a> ExprChecker<'a> for ActionChecker<'a> {
         self.instructions.push(Instruction::Literal(lit.clone()));
     }
 
-    fn infer_var(&mut self, sym: Symbol) -> Result<(Self::T, ArcSort), TypeError> {
+    fn infer_var(&mut self, span: Span, sym: Symbol) -> Result<(Self::T, ArcSort), TypeError> {
         if let Some((sort, _v, _ts)) = self.egraph().global_bindings.get(&sym) {
             self.instructions.push(Instruction::Global(sym));
             Ok(((), sort.clone()))
@@ -528,15 +628,18 @@ impl<'a> ExprChecker<'a> for ActionChecker<'a> {
             self.instructions.push(Instruction::Load(Load::Subst(i)));
             Ok(((), ty.clone()))
         } else {
-            Err(TypeError::Unbound(sym))
+            Err(TypeError::Unbound(sym, span))
         }
     }
 
     fn do_function(&mut self, f: Symbol, _args: Vec<Self::T>) -> Self::T {
         let func_type = self.egraph.desugar.type_info.func_types.get(&f).unwrap();
+        // calls to the constructors of a datatype with a `:merge` make a new e-class,
+        // even though the merge decides between the e-classes of two calls
+        let merged_constructor = self.egraph.desugar.merged_constructors.contains(&f);
         self.instructions.push(Instruction::CallFunction(
             f,
-            func_type.has_default || !func_type.has_merge,
+            func_type.has_default || !func_type.has_merge || merged_constructor,
         ));
     }
 
@@ -553,12 +656,12 @@ trait ExprChecker<'a> {
     fn do_function(&mut self, f: Symbol, args: Vec<Self::T>) -> Self::T;
     fn do_prim(&mut self, prim: Primitive, args: Vec<Self::T>) -> Self::T;
 
-    fn infer_var(&mut self, var: Symbol) -> Result<(Self::T, ArcSort), TypeError>;
-    fn check_var(&mut self, var: Symbol, ty: ArcSort) -> Result<Self::T, TypeError> {
-        let (t, actual) = self.infer_var(var)?;
+    fn infer_var(&mut self, span: Span, var: Symbol) -> Result<(Self::T, ArcSort), TypeError>;
+    fn check_var(&mut self, span: Span, var: Symbol, ty: ArcSort) -> Result<Self::T, TypeError> {
+        let (t, actual) = self.infer_var(span, var)?;
         if actual.name() != ty.name() {
             Err(TypeError::Mismatch {
-                expr: Expr::Var(var),
+                expr: Expr::Var(span, var),
                 expected: ty,
                 actual,
                 reason: "mismatch".into(),
@@ -570,7 +673,7 @@ trait ExprChecker<'a> {
 
     fn check_expr(&mut self, expr: &Expr, ty: ArcSort) -> Result<Self::T, TypeError> {
         match expr {
-            Expr::Var(v) if !self.is_variable(*v) => self.check_var(*v, ty),
+            Expr::Var(span, v) if !self.is_variable(*v) => self.check_var(*span, *v, ty),
             _ => {
                 let (t, actual) = self.infer_expr(expr)?;
                 if actual.name() != ty.name() {
@@ -593,12 +696,12 @@ trait ExprChecker<'a> {
 
     fn infer_expr(&mut self, expr: &Expr) -> Result<(Self::T, ArcSort), TypeError> {
         match expr {
-            Expr::Lit(lit) => {
+            Expr::Lit(_, lit) => {
                 let t = self.do_lit(lit);
                 Ok((t, self.egraph().desugar.type_info.infer_literal(lit)))
             }
-            Expr::Var(sym) => self.infer_var(*sym),
-            Expr::Call(sym, args) => {
+            Expr::Var(span, sym) => self.infer_var(*span, *sym),
+            Expr::Call(span, sym, args) => {
                 if let Some(functype) = self.egraph().desugar.type_info.func_types.get(sym) {
                     assert!(functype.input.len() == args.len());
 
@@ -628,6 +731,7 @@ trait ExprChecker<'a> {
                     Err(TypeError::NoMatchingPrimitive {
                         op: *sym,
                         inputs: tys.into_iter().map(|t| t.name()).collect(),
+                        span: *span,
                     })
                 } else {
                     panic!("Unbound function {}", sym);
@@ -652,8 +756,10 @@ enum Instruction {
     CallFunction(Symbol, bool),
     CallPrimitive(Primitive, usize),
     DeleteRow(Symbol),
+    SubsumeRow(Symbol),
     Set(Symbol),
     Union(usize),
+    Pin,
     Extract(usize),
     Panic(String),
     Pop,
@@ -773,12 +879,14 @@ impl EGraph {
                             }
                             _ => {
                                 return Err(Error::NotFoundError(NotFoundError(Expr::Var(
+                                    DUMMY_SPAN,
                                     format!("No value found for {f} {:?}", values).into(),
                                 ))))
                             }
                         }
                     } else {
                         return Err(Error::NotFoundError(NotFoundError(Expr::Var(
+                            DUMMY_SPAN,
                             format!("No value found for {f} {:?}", values).into(),
                         ))));
                     };
@@ -790,7 +898,7 @@ impl EGraph {
                 Instruction::CallPrimitive(p, arity) => {
                     let new_len = stack.len() - arity;
                     let values = &stack[new_len..];
-                    if let Some(value) = p.apply(values) {
+                    if let Some(value) = p.apply_with_egraph(values, self) {
                         stack.truncate(new_len);
                         stack.push(value);
                     } else {
@@ -843,6 +951,20 @@ impl EGraph {
                                 // Why?
                                 self.run_actions(&mut Vec::new(), &values, &prog, true)?;
                             }
+                            // after `on_merge`, as when rebuilding
+                            if self.check_merge_order {
+                                if let MergeFn::Expr(merge_prog) =
+                                    self.functions[f].merge.merge_vals.clone()
+                                {
+                                    self.verify_merge_order(
+                                        *f,
+                                        &merge_prog,
+                                        &stack[new_len..],
+                                        old_value,
+                                        new_value,
+                                    )?;
+                                }
+                            }
                         }
                     } else {
                         function.insert(args, new_value, self.timestamp);
@@ -860,6 +982,11 @@ impl EGraph {
                     });
                     stack.truncate(new_len);
                 }
+                Instruction::Pin => {
+                    let value = stack.pop().unwrap();
+                    let id = Id::from(value.bits as usize);
+                    self.unionfind.pin(id, value.tag);
+                }
                 Instruction::Extract(arity) => {
                     let new_len = stack.len() - arity;
                     let values = &stack[new_len..];
@@ -869,6 +996,7 @@ impl EGraph {
                     assert!(num_sort.to_string() == "i64");
 
                     let variants = values[1].bits as i64;
+                    let eclass_size = self.eclass_size(values[0]);
                     if variants == 0 {
                         let (cost, expr) = self.extract(
                             values[0],
@@ -882,13 +1010,21 @@ impl EGraph {
                             termdag,
                             cost,
                             expr,
+                            eclass_size,
                         });
                     } else {
                         if variants < 0 {
                             panic!("Cannot extract negative number of variants");
                         }
-                        let extracted =
-                            self.extract_variants(values[0], variants as usize, &mut termdag);
+                        let extracted: Vec<_> = self
+                            .extract_variants_with_termdag(
+                                values[0],
+                                variants as usize,
+                                &mut termdag,
+                            )
+                            .into_iter()
+                            .map(|(_cost, term)| term)
+                            .collect();
                         log::info!("extracted variants:");
                         let mut msg = String::default();
                         msg += "(\n";
@@ -903,16 +1039,23 @@ impl EGraph {
                         self.extract_report = Some(ExtractReport::Variants {
                             termdag,
                             variants: extracted,
+                            eclass_size,
                         });
                     }
 
                     stack.truncate(new_len);
                 }
-                Instruction::Panic(msg) => panic!("Panic: {}", msg),
+                Instruction::Panic(msg) => {
+                    if self.recoverable_panics {
+                        return Err(Error::UserPanic(msg.clone(), subst.to_vec()));
+                    }
+                    panic!("Panic: {}", msg)
+                }
                 Instruction::Literal(lit) => match lit {
                     Literal::Int(i) => stack.push(Value::from(*i)),
                     Literal::F64(f) => stack.push(Value::from(*f)),
-                    Literal::String(s) => stack.push(Value::from(*s)),
+                    Literal::String(_) => stack.push(self.eval_lit(lit)),
+                    Literal::Bool(b) => stack.push(Value::from(*b)),
                     Literal::Unit => stack.push(Value::unit()),
                 },
                 Instruction::Pop => {
@@ -925,6 +1068,13 @@ impl EGraph {
                     function.remove(args, self.timestamp);
                     stack.truncate(new_len);
                 }
+                Instruction::SubsumeRow(f) => {
+                    let function = self.functions.get_mut(f).unwrap();
+                    let new_len = stack.len() - function.schema.input.len();
+                    let args = &stack[new_len..];
+                    function.subsume(args);
+                    stack.truncate(new_len);
+                }
             }
         }
         Ok(())
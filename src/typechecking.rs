@@ -24,13 +24,15 @@ impl FuncType {
 #[derive(Clone)]
 pub struct TypeInfo {
     // get the sort from the sorts name()
-    pub presorts: HashMap<Symbol, PreSort>,
-    pub presort_names: HashSet<Symbol>,
-    pub sorts: HashMap<Symbol, Arc<dyn Sort>>,
-    pub primitives: HashMap<Symbol, Vec<Primitive>>,
-    pub func_types: HashMap<Symbol, FuncType>,
-    pub global_types: HashMap<Symbol, ArcSort>,
-    pub local_types: HashMap<CommandId, HashMap<Symbol, ArcSort>>,
+    pub(crate) presorts: HashMap<Symbol, PreSort>,
+    pub(crate) presort_names: HashSet<Symbol>,
+    pub(crate) sorts: HashMap<Symbol, Arc<dyn Sort>>,
+    // sorts whose primitives aren't registered yet
+    pub(crate) pending_sorts: Vec<ArcSort>,
+    pub(crate) primitives: HashMap<Symbol, Vec<Primitive>>,
+    pub(crate) func_types: HashMap<Symbol, FuncType>,
+    pub(crate) global_types: HashMap<Symbol, ArcSort>,
+    pub(crate) local_types: HashMap<CommandId, HashMap<Symbol, ArcSort>>,
 }
 
 impl Default for TypeInfo {
@@ -39,6 +41,7 @@ impl Default for TypeInfo {
             presorts: Default::default(),
             presort_names: Default::default(),
             sorts: Default::default(),
+            pending_sorts: Default::default(),
             primitives: Default::default(),
             func_types: Default::default(),
             global_types: Default::default(),
@@ -50,6 +53,14 @@ impl Default for TypeInfo {
         res.add_sort(I64Sort::new("i64".into()));
         res.add_sort(F64Sort::new("f64".into()));
         res.add_sort(RationalSort::new("Rational".into()));
+        res.add_sort(BoolSort::new("bool".into()));
+        res.add_sort(CharSort::new("Char".into()));
+        res.register_pending_primitives();
+
+        res.add_primitive(CountRows {
+            string: res.get_sort(),
+            i64: res.get_sort(),
+        });
 
         res.presort_names.extend(MapSort::presort_names());
         res.presort_names.extend(SetSort::presort_names());
@@ -71,6 +82,7 @@ impl TypeInfo {
             Literal::Int(_) => self.sorts.get(&Symbol::from("i64")),
             Literal::F64(_) => self.sorts.get(&Symbol::from("f64")),
             Literal::String(_) => self.sorts.get(&Symbol::from("String")),
+            Literal::Bool(_) => self.sorts.get(&Symbol::from("bool")),
             Literal::Unit => self.sorts.get(&Symbol::from("Unit")),
         }
         .unwrap()
@@ -81,6 +93,8 @@ impl TypeInfo {
         self.add_arcsort(Arc::new(sort)).unwrap()
     }
 
+    /// Adds a sort, deferring its primitives until
+    /// [`TypeInfo::register_pending_primitives`].
     pub fn add_arcsort(&mut self, sort: ArcSort) -> Result<(), TypeError> {
         let name = sort.name();
 
@@ -88,12 +102,22 @@ impl TypeInfo {
             Entry::Occupied(_) => Err(TypeError::SortAlreadyBound(name)),
             Entry::Vacant(e) => {
                 e.insert(sort.clone());
-                sort.register_primitives(self);
+                self.pending_sorts.push(sort);
                 Ok(())
             }
         }
     }
 
+    /// Registers the primitives of the sorts added since the last call, so
+    /// they can look up any of those sorts by name, regardless of the order
+    /// they were added in. This runs before each command that isn't a sort
+    /// declaration.
+    pub fn register_pending_primitives(&mut self) {
+        for sort in std::mem::take(&mut self.pending_sorts) {
+            sort.register_primitives(self);
+        }
+    }
+
     pub fn get_sort<S: Sort + Send + Sync>(&self) -> Arc<S> {
         for sort in self.sorts.values() {
             let sort = sort.clone().as_arc_any();
@@ -107,6 +131,20 @@ impl TypeInfo {
         panic!("Failed to lookup sort: {}", std::any::type_name::<S>());
     }
 
+    /// All the sorts declared so far, in no particular order.
+    pub fn sorts(&self) -> impl Iterator<Item = &ArcSort> {
+        self.sorts.values()
+    }
+
+    /// The overloads registered under a primitive name, empty if there are none.
+    pub fn primitives_named(&self, name: Symbol) -> &[Primitive] {
+        self.primitives.get(&name).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn func_type(&self, name: Symbol) -> Option<&FuncType> {
+        self.func_types.get(&name)
+    }
+
     pub fn add_primitive(&mut self, prim: impl Into<Primitive>) {
         let prim = prim.into();
         self.primitives.entry(prim.name()).or_default().push(prim);
@@ -132,14 +170,14 @@ impl TypeInfo {
                 if let Some(sort) = self.sorts.get(name) {
                     Ok(sort.clone())
                 } else {
-                    Err(TypeError::Unbound(*name))
+                    Err(TypeError::Unbound(*name, DUMMY_SPAN))
                 }
             })
             .collect::<Result<Vec<_>, _>>()?;
         let output = if let Some(sort) = self.sorts.get(&func.schema.output) {
             Ok(sort.clone())
         } else {
-            Err(TypeError::Unbound(func.schema.output))
+            Err(TypeError::Unbound(func.schema.output, DUMMY_SPAN))
         }?;
         Ok(FuncType::new(
             input,
@@ -176,6 +214,11 @@ impl TypeInfo {
             NCommand::NormAction(action) => {
                 self.typecheck_action(id, action, true)?;
             }
+            NCommand::NormActions(actions) => {
+                for action in actions {
+                    self.typecheck_action(id, action, true)?;
+                }
+            }
             NCommand::Check(facts) => {
                 self.typecheck_facts(id, facts)?;
                 self.verify_normal_form_facts(facts);
@@ -186,6 +229,11 @@ impl TypeInfo {
             NCommand::RunSchedule(schedule) => {
                 self.typecheck_schedule(id, schedule)?;
             }
+            NCommand::CheckIn(config, facts) => {
+                self.typecheck_schedule(id, &NormSchedule::Run(config.clone()))?;
+                self.typecheck_facts(id, facts)?;
+                self.verify_normal_form_facts(facts);
+            }
 
             // TODO cover all cases in typechecking
             _ => (),
@@ -207,11 +255,11 @@ impl TypeInfo {
                     self.typecheck_schedule(ctx, schedule)?;
                 }
             }
-            NormSchedule::Saturate(schedule) => {
+            NormSchedule::Saturate(schedule) | NormSchedule::Timeout(_, schedule) => {
                 self.typecheck_schedule(ctx, schedule)?;
             }
             NormSchedule::Run(run_config) => {
-                if let Some(facts) = &run_config.until {
+                for facts in run_config.until.iter().flatten() {
                     self.typecheck_facts(ctx, facts)?;
                     self.verify_normal_form_facts(facts);
                 }
@@ -226,6 +274,10 @@ impl TypeInfo {
             .local_types
             .insert(command.metadata.id, Default::default())
             .is_none());
+        // a run of sort declarations registers its primitives together
+        if !matches!(command.command, NCommand::Sort(..)) {
+            self.register_pending_primitives();
+        }
         self.typecheck_ncommand(&command.command, command.metadata.id)
     }
 
@@ -241,6 +293,13 @@ impl TypeInfo {
 
         let sort = match presort_and_args {
             Some((presort, args)) => {
+                if args
+                    .iter()
+                    .flat_map(|arg| arg.vars())
+                    .any(|arg| arg == name)
+                {
+                    return Err(TypeError::CyclicSort(name));
+                }
                 let mksort = self
                     .presorts
                     .get(presort)
@@ -317,6 +376,15 @@ impl TypeInfo {
                         panic!("ConstrainEq on unbound variables");
                     }
                 }
+                NormFact::Absent(NormExpr::Call(_head, body)) => {
+                    body.iter().for_each(|bvar| {
+                        assert!(
+                            let_bound.contains(bvar) || self.global_types.contains_key(bvar),
+                            "Expected {} to be bound",
+                            bvar
+                        );
+                    });
+                }
             }
         }
         let_bound
@@ -351,7 +419,8 @@ impl TypeInfo {
                 NormAction::LetLit(v1, _lit) => {
                     assert!(let_bound.insert(*v1));
                 }
-                NormAction::Delete(NormExpr::Call(_head, body)) => {
+                NormAction::Delete(NormExpr::Call(_head, body))
+                | NormAction::Subsume(NormExpr::Call(_head, body)) => {
                     body.iter().for_each(|bvar| {
                         assert_bound(bvar, let_bound);
                     });
@@ -370,6 +439,7 @@ impl TypeInfo {
                     assert_bound(v1, let_bound);
                     assert_bound(v2, let_bound);
                 }
+                NormAction::Pin(var) => assert_bound(var, let_bound),
                 NormAction::Panic(..) => (),
             }
         }
@@ -414,7 +484,7 @@ impl TypeInfo {
                 let lit_type = self.infer_literal(lit);
                 self.introduce_binding(ctx, *var, lit_type, is_global)?;
             }
-            NormAction::Delete(expr) => {
+            NormAction::Delete(expr) | NormAction::Subsume(expr) => {
                 self.typecheck_expr(ctx, expr, true)?;
             }
             NormAction::Set(expr, other) => {
@@ -431,6 +501,12 @@ impl TypeInfo {
                     return Err(TypeError::TypeMismatch(var1_type, var2_type));
                 }
             }
+            NormAction::Pin(var) => {
+                let var_type = self.lookup(ctx, *var)?;
+                if !var_type.is_eq_sort() {
+                    return Err(TypeError::PinPrimitive(var_type));
+                }
+            }
             NormAction::Extract(_var, _variants) => {}
             NormAction::LetVar(var1, var2) => {
                 let var2_type = self.lookup(ctx, *var2)?;
@@ -509,9 +585,16 @@ impl TypeInfo {
                         .unwrap()
                         .insert(*var1, v2type);
                 } else {
-                    return Err(TypeError::Unbound(*var1));
+                    return Err(TypeError::Unbound(*var1, DUMMY_SPAN));
                 }
             }
+            NormFact::Absent(expr @ NormExpr::Call(head, _body)) => {
+                // only rows of tables can be absent, not results of primitives
+                if !self.func_types.contains_key(head) {
+                    return Err(TypeError::UnboundFunction(*head));
+                }
+                self.typecheck_expr(ctx, expr, true)?;
+            }
         }
         Ok(())
     }
@@ -537,7 +620,7 @@ impl TypeInfo {
                 if let Some(found) = self.local_types.get(&ctx).unwrap().get(&sym) {
                     Ok(found.clone())
                 } else {
-                    Err(TypeError::Unbound(sym))
+                    Err(TypeError::Unbound(sym, DUMMY_SPAN))
                 }
             })
     }
@@ -585,6 +668,7 @@ impl TypeInfo {
             Err(TypeError::NoMatchingPrimitive {
                 op: sym,
                 inputs: input_types.iter().map(|s| s.name()).collect(),
+                span: DUMMY_SPAN,
             })
         }
     }
@@ -643,8 +727,8 @@ pub enum TypeError {
     },
     #[error("Tried to unify too many literals: {}", ListDisplay(.0, "\n"))]
     TooManyLiterals(Vec<Literal>),
-    #[error("Unbound symbol {0}")]
-    Unbound(Symbol),
+    #[error("Unbound symbol {0}{}", .1.at())]
+    Unbound(Symbol, Span),
     #[error("Undefined sort {0}")]
     UndefinedSort(Symbol),
     #[error("Unbound function {0}")]
@@ -667,12 +751,103 @@ pub enum TypeError {
     TypeMismatch(ArcSort, ArcSort),
     #[error("Presort {0} not found.")]
     PresortNotFound(Symbol),
+    #[error("Sort {0} refers to itself in its own arguments.")]
+    CyclicSort(Symbol),
     #[error("Cannot type a variable as unit: {0}")]
     UnitVar(Symbol),
     #[error("Failed to infer a type for: {0}")]
     InferenceFailure(Expr),
-    #[error("No matching primitive for: ({op} {})", ListDisplay(.inputs, " "))]
-    NoMatchingPrimitive { op: Symbol, inputs: Vec<Symbol> },
+    #[error("No matching primitive for: ({op} {}){}", ListDisplay(.inputs, " "), .span.at())]
+    NoMatchingPrimitive {
+        op: Symbol,
+        inputs: Vec<Symbol>,
+        span: Span,
+    },
     #[error("Variable {0} was already defined")]
     AlreadyDefined(Symbol),
+    #[error("Cannot pin a value of sort {}, only eq sorts have representatives", .0.name())]
+    PinPrimitive(ArcSort),
+}
+
+impl TypeError {
+    // errors naming a concrete reason come first, failures to infer a type last
+    fn specificity(&self) -> usize {
+        match self {
+            TypeError::Mismatch { .. } | TypeError::Arity { .. } | TypeError::TypeMismatch(..) => 0,
+            TypeError::InferenceFailure(_) => 2,
+            _ => 1,
+        }
+    }
+
+    /// Errors found on the desugared program have no span. This points them
+    /// at the first place in `command` that uses the symbol they are about.
+    pub(crate) fn locate(self, command: &Command) -> Self {
+        let first_span = |matches: &dyn Fn(&Expr) -> bool| {
+            let mut found = DUMMY_SPAN;
+            for expr in command.exprs() {
+                expr.walk(
+                    &mut |e| {
+                        if found.is_dummy() && matches(e) {
+                            found = e.span();
+                        }
+                    },
+                    &mut |_| {},
+                );
+            }
+            found
+        };
+        match self {
+            TypeError::Unbound(sym, span) if span.is_dummy() => {
+                let span = first_span(
+                    &|e| matches!(e, Expr::Var(_, v) | Expr::Call(_, v, _) if *v == sym),
+                );
+                TypeError::Unbound(sym, span)
+            }
+            TypeError::NoMatchingPrimitive { op, inputs, span } if span.is_dummy() => {
+                let span = first_span(&|e| matches!(e, Expr::Call(_, f, _) if *f == op));
+                TypeError::NoMatchingPrimitive { op, inputs, span }
+            }
+            err => err,
+        }
+    }
+}
+
+/// The distinct messages of `errors`, most specific first and otherwise in order.
+pub(crate) fn ranked_type_errors(errors: &[TypeError]) -> Vec<String> {
+    let mut ranked: Vec<(usize, String)> = vec![];
+    for err in errors {
+        let msg = err.to_string();
+        if !ranked.iter().any(|(_, seen)| *seen == msg) {
+            ranked.push((err.specificity(), msg));
+        }
+    }
+    ranked.sort_by_key(|(specificity, _)| *specificity);
+    ranked.into_iter().map(|(_, msg)| msg).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_type_errors_ranked_and_deduped() {
+        let i64_sort: ArcSort = Arc::new(I64Sort::new("i64".into()));
+        let string_sort: ArcSort = Arc::new(StringSort::new("String".into()));
+        let inference_failure = TypeError::InferenceFailure(Expr::Var(DUMMY_SPAN, "x".into()));
+        let err = Error::TypeErrors(vec![
+            inference_failure.clone(),
+            TypeError::Mismatch {
+                expr: Expr::Var(DUMMY_SPAN, "y".into()),
+                expected: i64_sort,
+                actual: string_sort,
+                reason: "mismatch".into(),
+            },
+            inference_failure,
+        ]);
+        let msg = err.to_string();
+        let lines: Vec<&str> = msg.lines().collect();
+        assert_eq!(lines.len(), 3, "{msg}");
+        assert!(lines[1].starts_with("Type mismatch"), "{msg}");
+        assert!(lines[2].starts_with("Failed to infer"), "{msg}");
+    }
 }
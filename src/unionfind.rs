@@ -2,7 +2,7 @@
 //! halving for compression.
 //!
 //! This implementation uses interior mutability for `find`.
-use crate::util::HashMap;
+use crate::util::{HashMap, HashSet};
 use crate::{Id, Symbol, Value};
 
 use std::cell::Cell;
@@ -16,6 +16,7 @@ pub struct UnionFind {
     n_unions: usize,
     recent_ids: HashMap<Symbol, Vec<Id>>,
     staged_ids: HashMap<Symbol, Vec<Id>>,
+    pinned: HashSet<Id>,
 }
 
 impl UnionFind {
@@ -109,6 +110,27 @@ impl UnionFind {
         res
     }
 
+    /// Make the given [`Id`] the canonical representative of its class, and
+    /// prefer it as the representative in later unions.
+    ///
+    /// A class keeps the first id pinned in it, so pinning another id of a
+    /// class that is already pinned does nothing. If two pinned classes are
+    /// merged, the one passed first to [`union`] is kept.
+    ///
+    /// [`union`]: UnionFind::union
+    pub fn pin(&mut self, id: Id, sort: Symbol) {
+        let root = self.find(id);
+        if self.pinned.contains(&root) {
+            return;
+        }
+        if root != id {
+            self.parent(id).set(id);
+            self.parent(root).set(id);
+            self.staged_ids.entry(sort).or_default().push(root);
+        }
+        self.pinned.insert(id);
+    }
+
     /// Merge the underlying equivalence classes for the two ids.
     ///
     /// This method does not update any metadata related to timestamps or sorts;
@@ -120,8 +142,11 @@ impl UnionFind {
     }
 
     fn do_union(&mut self, id1: Id, id2: Id) -> (Id, Option<Id>) {
-        let id1 = self.find(id1);
-        let id2 = self.find(id2);
+        let mut id1 = self.find(id1);
+        let mut id2 = self.find(id2);
+        if self.pinned.contains(&id2) && !self.pinned.contains(&id1) {
+            mem::swap(&mut id1, &mut id2);
+        }
         if id1 != id2 {
             self.parent(id2).set(id1);
             self.n_unions += 1;
@@ -176,4 +201,31 @@ mod tests {
         let expected = vec![0, 0, 0, 0, 4, 5, 6, 6, 6, 6];
         assert_eq!(uf.parents, ids(expected));
     }
+
+    #[test]
+    fn pinned_representative() {
+        let id = Id::from;
+        let sort = Symbol::from("Math");
+
+        let mut uf = UnionFind::default();
+        for _ in 0..4 {
+            uf.make_set();
+        }
+
+        // pinning a non-root makes it the root
+        uf.union(id(0), id(1), sort);
+        uf.pin(id(1), sort);
+        assert_eq!(uf.find(id(0)), id(1));
+
+        // the pinned root survives unions in either order
+        uf.union(id(2), id(0), sort);
+        assert_eq!(uf.find(id(2)), id(1));
+        uf.union(id(1), id(3), sort);
+        assert_eq!(uf.find(id(3)), id(1));
+
+        // a second pin in the same class keeps the first
+        uf.pin(id(3), sort);
+        assert_eq!(uf.find(id(3)), id(1));
+        assert_eq!(uf.find(id(0)), id(1));
+    }
 }
@@ -1,5 +1,4 @@
 use ordered_float::OrderedFloat;
-use std::num::NonZeroU32;
 
 use lazy_static::lazy_static;
 
@@ -58,11 +57,11 @@ impl From<OrderedFloat<f64>> for Value {
     }
 }
 
-impl From<Symbol> for Value {
-    fn from(s: Symbol) -> Self {
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
         Self {
-            tag: Symbol::from("String"),
-            bits: NonZeroU32::from(s).get().into(),
+            tag: Symbol::from("bool"),
+            bits: b as u64,
         }
     }
 }
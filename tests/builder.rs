@@ -0,0 +1,132 @@
+use egglog::{ast::*, *};
+
+fn num(n: i64) -> Expr {
+    Expr::call("Num", [Expr::lit(n)])
+}
+
+fn add(a: Expr, b: Expr) -> Expr {
+    Expr::call("Add", [a, b])
+}
+
+#[test]
+fn test_build_program_without_parsing() {
+    let mut egraph = EGraph::default();
+    let variant = |name: &str, types: &[&str]| Variant {
+        name: name.into(),
+        types: types.iter().map(|t| (*t).into()).collect(),
+        fields: vec![None; types.len()],
+        cost: None,
+    };
+    egraph
+        .add_datatype(
+            "Math",
+            vec![variant("Num", &["i64"]), variant("Add", &["Math", "Math"])],
+        )
+        .unwrap();
+    egraph
+        .run_ast_command(Command::AddRuleset("comm".into()))
+        .unwrap();
+    egraph
+        .add_rewrite(
+            "comm",
+            Rewrite {
+                lhs: add(
+                    Expr::Var(DUMMY_SPAN, "a".into()),
+                    Expr::Var(DUMMY_SPAN, "b".into()),
+                ),
+                rhs: add(
+                    Expr::Var(DUMMY_SPAN, "b".into()),
+                    Expr::Var(DUMMY_SPAN, "a".into()),
+                ),
+                conditions: vec![],
+            },
+        )
+        .unwrap();
+    egraph
+        .add_actions(vec![Action::Let("e".into(), add(num(1), num(2)))])
+        .unwrap();
+    egraph
+        .run_ast_schedule(Schedule::Repeat(
+            1,
+            Box::new(Schedule::Run(RunConfig {
+                ruleset: "comm".into(),
+                until: None,
+                until_mode: UntilMode::All,
+                dry_run: false,
+            })),
+        ))
+        .unwrap();
+    egraph
+        .check_ast_facts(vec![Fact::Eq(vec![
+            Expr::Var(DUMMY_SPAN, "e".into()),
+            add(num(2), num(1)),
+        ])])
+        .unwrap();
+
+    // the same program built from a string leaves the same tables behind
+    let mut parsed = EGraph::default();
+    parsed
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (ruleset comm)
+             (rewrite (Add a b) (Add b a) :ruleset comm)
+             (let e (Add (Num 1) (Num 2)))
+             (run comm 1)",
+        )
+        .unwrap();
+    assert_eq!(egraph.num_tuples(), parsed.num_tuples());
+}
+
+#[test]
+fn test_ast_rule_is_desugared() {
+    let mut egraph = EGraph::default();
+    egraph
+        .add_function(FunctionDecl::relation(
+            "edge".into(),
+            vec!["i64".into(), "i64".into()],
+        ))
+        .unwrap();
+    egraph
+        .add_function(FunctionDecl::relation(
+            "path".into(),
+            vec!["i64".into(), "i64".into()],
+        ))
+        .unwrap();
+    egraph
+        .add_ast_rule(
+            "",
+            "base",
+            Rule {
+                body: vec![Fact::Fact(Expr::call(
+                    "edge",
+                    [
+                        Expr::Var(DUMMY_SPAN, "x".into()),
+                        Expr::Var(DUMMY_SPAN, "y".into()),
+                    ],
+                ))],
+                head: vec![Action::Expr(Expr::call(
+                    "path",
+                    [
+                        Expr::Var(DUMMY_SPAN, "x".into()),
+                        Expr::Var(DUMMY_SPAN, "y".into()),
+                    ],
+                ))],
+            },
+        )
+        .unwrap();
+    egraph
+        .add_actions(vec![Action::Expr(Expr::call(
+            "edge",
+            [Expr::lit(1i64), Expr::lit(2i64)],
+        ))])
+        .unwrap();
+    egraph
+        .run_ast_schedule(Schedule::Run(RunConfig {
+            ruleset: "".into(),
+            until: None,
+            until_mode: UntilMode::All,
+            dry_run: false,
+        }))
+        .unwrap();
+    assert_eq!(egraph.function_size(Some("path".into())).unwrap(), 1);
+}
@@ -0,0 +1,46 @@
+use egglog::*;
+
+#[test]
+fn test_failing_check_in_pops_its_scope() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (rewrite (Add a b) (Add b a))
+             (let e (Add (Num 1) (Num 2)))",
+        )
+        .unwrap();
+    let before = egraph.num_tuples();
+    assert!(egraph
+        .parse_and_run_program("(check-in (run) (= e (Num 3)))")
+        .is_err());
+    assert_eq!(egraph.num_tuples(), before);
+    // the rewrite's results were dropped along with the scope
+    assert!(egraph
+        .parse_and_run_program("(check (= e (Add (Num 2) (Num 1))))")
+        .is_err());
+    // and nothing is left pushed
+    assert!(egraph.parse_and_run_program("(pop)").is_err());
+}
+
+#[test]
+fn test_check_in_sees_unions_from_the_run() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (function left (Math) i64)
+             (function right (Math) i64)
+             (set (left (Num 1)) 7)
+             (set (right (Num 2)) 8)
+             (rule ((= a (Num 1)) (= b (Num 2))) ((union a b)))",
+        )
+        .unwrap();
+    // whichever e-class the union keeps, one of the rows is only found after a rebuild
+    egraph
+        .parse_and_run_program("(check-in (run) (= (left (Num 2)) 7) (= (right (Num 1)) 8))")
+        .unwrap();
+    assert!(egraph
+        .parse_and_run_program("(check (= (left (Num 2)) 7))")
+        .is_err());
+}
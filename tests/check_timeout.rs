@@ -0,0 +1,21 @@
+use egglog::*;
+
+#[test]
+fn test_check_timeout_stops_within_an_iteration() {
+    let mut egraph = EGraph::default();
+    let edges: String = (0..100_000).map(|i| format!("(edge {i})\n")).collect();
+    egraph
+        .parse_and_run_program(&format!(
+            "(relation edge (i64))
+             (relation seen (i64))
+             {edges}
+             (rule ((edge x)) ((seen x)))
+             (set-option check_timeout 1)"
+        ))
+        .unwrap();
+    // the facts never hold, and the only iteration takes longer than the timeout
+    let res = egraph.parse_and_run_program("(run-schedule (saturate (run :until (seen -1))))");
+    assert!(matches!(res, Err(Error::CheckTimeout(_))));
+    // the run stopped before applying the rule to every match
+    assert!(egraph.function_size(Some("seen".into())).unwrap() < 100_000);
+}
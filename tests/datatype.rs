@@ -0,0 +1,30 @@
+use egglog::*;
+
+#[test]
+fn test_field_accessor_collision() {
+    let mut egraph = EGraph::default();
+    let err = egraph
+        .parse_and_run_program(
+            "(function Pt-x (i64) i64)
+             (datatype Point (Pt (x i64)))",
+        )
+        .unwrap_err();
+    assert!(matches!(err, Error::TypeError(_)), "{err}");
+    assert!(err.to_string().contains("Pt-x"), "{err}");
+}
+
+#[test]
+fn test_merged_constructors_after_push() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) :merge old)
+             (push)
+             (let three (Num 3))",
+        )
+        .unwrap();
+    // the pushed scope still knows the constructor makes new e-classes
+    egraph
+        .parse_and_run_program("(check (= three (Num 3)))")
+        .unwrap();
+}
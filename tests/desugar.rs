@@ -0,0 +1,195 @@
+use egglog::{ast::*, *};
+
+#[test]
+fn test_rule_names_in_metadata() {
+    let mut egraph = EGraph::default();
+    let program = egraph
+        .parse_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (rule ((Num n)) ((Add (Num n) (Num n))) :name \"double\")
+             (rewrite (Add a b) (Add b a))
+             (birewrite (Add a (Num 0)) a)",
+        )
+        .unwrap();
+    let desugared = egraph
+        .process_commands(program, CompilerPassStop::Desugar)
+        .unwrap();
+    let mut rule_names: Vec<(Symbol, Option<Symbol>)> = desugared
+        .iter()
+        .filter_map(|cmd| match &cmd.command {
+            NCommand::NormRule { name, .. } => Some((*name, cmd.metadata.rule_name)),
+            _ => None,
+        })
+        .collect();
+    // the semi-naive transform may add a copy of a rule under the same name
+    rule_names.dedup();
+    assert_eq!(
+        rule_names,
+        vec![
+            ("double".into(), Some("double".into())),
+            (
+                "(rewrite (Add a b) (Add b a))".into(),
+                Some("(rewrite (Add a b) (Add b a))".into())
+            ),
+            (
+                "(rewrite (Add a (Num 0)) a)=>".into(),
+                Some("(rewrite (Add a (Num 0)) a)".into())
+            ),
+            (
+                "(rewrite (Add a (Num 0)) a)<=".into(),
+                Some("(rewrite (Add a (Num 0)) a)".into())
+            ),
+        ]
+    );
+    assert!(desugared
+        .iter()
+        .filter(|cmd| !matches!(cmd.command, NCommand::NormRule { .. }))
+        .all(|cmd| cmd.metadata.rule_name.is_none()));
+}
+
+#[test]
+fn test_share_subexpressions_across_commands() {
+    let mut egraph = EGraph::default();
+    egraph.set_share_subexpressions(true);
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (let a (Add (Num 1) (Num 2)))",
+        )
+        .unwrap();
+    let program = egraph
+        .parse_program("(let b (Add (Add (Num 1) (Num 2)) (Num 3)))")
+        .unwrap();
+    let desugared = egraph
+        .process_commands(program, CompilerPassStop::Desugar)
+        .unwrap();
+    let literals: Vec<Literal> = desugared
+        .iter()
+        .filter_map(|cmd| match &cmd.command {
+            NCommand::NormAction(NormAction::LetLit(_, lit)) => Some(lit.clone()),
+            _ => None,
+        })
+        .collect();
+    // only the new part of the expression is flattened again
+    assert_eq!(literals, vec![Literal::Int(3)]);
+}
+
+#[test]
+fn test_shared_subexpressions_dropped_at_pop() {
+    let mut egraph = EGraph::default();
+    egraph.set_share_subexpressions(true);
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (push)
+             (let a (Add (Num 1) (Num 2)))
+             (pop)
+             (let b (Add (Num 1) (Num 2)))
+             (check (= b (Add (Num 1) (Num 2))))
+             (calc () (Add (Num 3) (Num 4)) (Add (Num 3) (Num 4)))
+             (let c (Add (Num 3) (Num 4)))
+             (check (= c (Add (Num 3) (Num 4))))",
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_shared_subexpressions_dropped_after_run() {
+    let mut egraph = EGraph::default();
+    egraph.set_share_subexpressions(true);
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (let a (Add (Num 1) (Num 2)))
+             (rule ((Add x y)) ((delete (Add x y))))
+             (run 1)
+             (let b (Add (Num 1) (Num 2)))
+             (check (= b (Add (Num 1) (Num 2))))",
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_identical_rewrites_get_distinct_names() {
+    let mut egraph = EGraph::default();
+    let program = egraph
+        .parse_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (ruleset a)
+             (ruleset b)
+             (rewrite (Add x y) (Add y x) :ruleset a)
+             (rewrite (Add x y) (Add y x) :ruleset b)
+             (rewrite (Add x y) (Add y x) :ruleset b)",
+        )
+        .unwrap();
+    let names: Vec<(Symbol, Symbol)> = egraph
+        .process_commands(program, CompilerPassStop::Desugar)
+        .unwrap()
+        .iter()
+        .filter_map(|cmd| match &cmd.command {
+            NCommand::NormRule { ruleset, name, .. } => Some((*ruleset, *name)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (
+                "a".into(),
+                "(rewrite (Add x y) (Add y x) :ruleset a)".into()
+            ),
+            (
+                "b".into(),
+                "(rewrite (Add x y) (Add y x) :ruleset b)".into()
+            ),
+            (
+                "b".into(),
+                "(rewrite (Add x y) (Add y x) :ruleset b) #1".into()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_actions_desugar_to_one_command() {
+    let mut egraph = EGraph::default();
+    let program = egraph
+        .parse_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (actions (let two (Num 2)) (Add two (Num 1)))",
+        )
+        .unwrap();
+    let desugared = egraph
+        .process_commands(program, CompilerPassStop::Desugar)
+        .unwrap();
+    let batches: Vec<usize> = desugared
+        .iter()
+        .filter_map(|cmd| match &cmd.command {
+            NCommand::NormActions(actions) => Some(actions.len()),
+            NCommand::NormAction(_) => Some(1),
+            _ => None,
+        })
+        .collect();
+    // the whole flattened batch is a single command
+    assert_eq!(batches.len(), 1);
+    assert!(batches[0] > 2);
+}
+
+#[test]
+fn test_duplicate_rule_name_is_an_error() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (ruleset other)
+             (rule ((Num n)) ((Add (Num n) (Num n))) :name \"r\")
+             (rule ((Num n)) ((Num (+ n 1))) :ruleset other :name \"r\")",
+        )
+        .unwrap();
+    let result = egraph.parse_and_run_program("(rule ((Add a b)) ((Add b a)) :name \"r\")");
+    assert!(result.is_err());
+    assert!(matches!(
+        result,
+        Err(Error::DuplicateRuleName(ruleset, name)) if ruleset.as_str() == "" && name.as_str() == "r"
+    ));
+}
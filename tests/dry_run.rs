@@ -0,0 +1,22 @@
+use egglog::{ast::Symbol, *};
+
+#[test]
+fn test_dry_run_keeps_the_timestamp() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (rewrite (Add a b) (Add b a))
+             (let x (Add (Num 1) (Num 2)))
+             (run 3 :dry-run)
+             (let y (Num 3))",
+        )
+        .unwrap();
+    let timestamp = |egraph: &EGraph, name: &str| egraph.global_bindings[&Symbol::from(name)].2;
+    assert_eq!(timestamp(&egraph, "x"), timestamp(&egraph, "y"));
+
+    egraph
+        .parse_and_run_program("(run 1) (let z (Num 4))")
+        .unwrap();
+    assert!(timestamp(&egraph, "z") > timestamp(&egraph, "y"));
+}
@@ -0,0 +1,176 @@
+use egglog::{ast::*, *};
+
+#[test]
+fn test_extract_report_eclass_size() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (let x (Add (Num 1) (Num 2)))
+             (union x (Add (Num 2) (Num 1)))
+             (extract x)",
+        )
+        .unwrap();
+    match egraph.get_extract_report() {
+        Some(ExtractReport::Best { eclass_size, .. }) => assert_eq!(*eclass_size, 2),
+        report => panic!("Expected a best extraction, got {report:?}"),
+    }
+}
+
+#[test]
+fn test_extract_report_eclass_size_skips_subsumed() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (let x (Add (Num 1) (Num 2)))
+             (union x (Add (Num 2) (Num 1)))
+             (union x (Num 3))
+             (subsume (Add (Num 2) (Num 1)))
+             (extract x)",
+        )
+        .unwrap();
+    match egraph.get_extract_report() {
+        Some(ExtractReport::Best { eclass_size, .. }) => assert_eq!(*eclass_size, 2),
+        report => panic!("Expected a best extraction, got {report:?}"),
+    }
+}
+
+#[test]
+fn test_extract_avoids_costly_declare() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (declare big Math :cost 1000)
+             (union big (Num 1))
+             (extract big)",
+        )
+        .unwrap();
+    match egraph.get_extract_report() {
+        Some(ExtractReport::Best { termdag, expr, .. }) => {
+            assert_eq!(termdag.to_string(expr), "(Num 1)")
+        }
+        report => panic!("Expected a best extraction, got {report:?}"),
+    }
+}
+
+#[test]
+fn test_extract_cheap_declare() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (declare small Math :cost 1)
+             (union small (Add (Num 1) (Num 2)))
+             (extract small)",
+        )
+        .unwrap();
+    match egraph.get_extract_report() {
+        Some(ExtractReport::Best { cost, .. }) => assert_eq!(*cost, 1),
+        report => panic!("Expected a best extraction, got {report:?}"),
+    }
+}
+
+#[test]
+fn test_extract_variants_api() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math) (Neg Math :cost 10))
+             (function Hidden (Math) Math :unextractable)
+             (let x (Add (Num 1) (Num 2)))
+             (union x (Neg (Num 3)))
+             (union x (Hidden (Num 4)))",
+        )
+        .unwrap();
+    let (_sort, x) = egraph
+        .eval_expr(&Expr::Var(DUMMY_SPAN, "x".into()), None, false)
+        .unwrap();
+
+    let variants: Vec<_> = egraph
+        .extract_variants(x, 5)
+        .into_iter()
+        .map(|(cost, expr)| (cost, expr.to_string()))
+        .collect();
+    assert_eq!(
+        variants,
+        vec![
+            (5, "(Add (Num 1) (Num 2))".into()),
+            (12, "(Neg (Num 3))".into())
+        ]
+    );
+    assert_eq!(egraph.extract_variants(x, 1).len(), 1);
+}
+
+#[test]
+fn test_extract_skips_subsumed() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (let x (Add (Num 1) (Num 2)))
+             (union x (Add (Num 2) (Num 1)))
+             (subsume (Add (Num 1) (Num 2)))
+             (extract x)",
+        )
+        .unwrap();
+    match egraph.get_extract_report() {
+        Some(ExtractReport::Best { termdag, expr, .. }) => {
+            assert_eq!(termdag.to_string(expr), "(Add (Num 2) (Num 1))")
+        }
+        report => panic!("Expected a best extraction, got {report:?}"),
+    }
+}
+
+#[test]
+fn test_extract_with_cost_fn() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math) (Big Math :cost 100))
+             (let x (Big (Num 1)))
+             (union x (Add (Num 1) (Add (Num 1) (Num 1))))",
+        )
+        .unwrap();
+    let (_sort, x) = egraph
+        .eval_expr(&Expr::Var(DUMMY_SPAN, "x".into()), None, false)
+        .unwrap();
+
+    let (cost, expr) = egraph.extract_with(x, declared_cost);
+    assert_eq!(
+        (cost, expr.to_string()),
+        (8, "(Add (Num 1) (Add (Num 1) (Num 1)))".into())
+    );
+    let node_count = |_decl: &FunctionDecl, children: &[Cost]| children.iter().sum::<Cost>() + 1;
+    let (cost, expr) = egraph.extract_with(x, node_count);
+    assert_eq!((cost, expr.to_string()), (3, "(Big (Num 1))".into()));
+}
+
+#[test]
+fn test_simplify_variants() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (rewrite (Add a b) (Add b a))
+             (simplify :variants 2 (run 1) (Add (Num 1) (Num 2)))",
+        )
+        .unwrap();
+    match egraph.get_extract_report() {
+        Some(ExtractReport::Variants {
+            termdag, variants, ..
+        }) => {
+            let mut variants: Vec<String> = variants
+                .iter()
+                .map(|term| termdag.to_string(term))
+                .collect();
+            variants.sort();
+            assert_eq!(
+                variants,
+                vec!["(Add (Num 1) (Num 2))", "(Add (Num 2) (Num 1))"]
+            );
+        }
+        report => panic!("Expected variants, got {report:?}"),
+    }
+}
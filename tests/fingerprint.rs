@@ -0,0 +1,71 @@
+use egglog::*;
+
+fn fingerprint(program: &str) -> u64 {
+    let mut egraph = EGraph::default();
+    egraph.parse_and_run_program(program).unwrap();
+    egraph.structural_fingerprint()
+}
+
+#[test]
+fn test_fingerprint_ignores_eclass_ids() {
+    let a = fingerprint(
+        "(datatype Math (Num i64) (Add Math Math))
+         (let x (Add (Num 1) (Num 2)))",
+    );
+    // same e-graph, but the e-classes are created in a different order
+    let b = fingerprint(
+        "(datatype Math (Num i64) (Add Math Math))
+         (let two (Num 2))
+         (let x (Add (Num 1) two))",
+    );
+    let c = fingerprint(
+        "(datatype Math (Num i64) (Add Math Math))
+         (let x (Add (Num 2) (Num 1)))",
+    );
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+}
+
+#[test]
+fn test_fingerprint_uses_string_contents() {
+    // the strings are interned in a different order in each e-graph
+    let a = fingerprint(
+        "(function label (i64) String)
+         (set (label 0) \"a\")
+         (set (label 1) \"b\")",
+    );
+    let b = fingerprint(
+        "(function label (i64) String)
+         (set (label 1) \"b\")
+         (set (label 0) \"a\")",
+    );
+    let c = fingerprint(
+        "(function label (i64) String)
+         (set (label 0) \"b\")
+         (set (label 1) \"a\")",
+    );
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+}
+
+#[test]
+fn test_fingerprint_sees_unions() {
+    let a = fingerprint(
+        "(datatype Math (Num i64) (Add Math Math))
+         (union (Add (Num 1) (Num 2)) (Add (Num 2) (Num 1)))",
+    );
+    // isomorphic to `a`, with the e-classes made in the opposite order
+    let b = fingerprint(
+        "(datatype Math (Num i64) (Add Math Math))
+         (Num 2)
+         (union (Add (Num 2) (Num 1)) (Add (Num 1) (Num 2)))",
+    );
+    // the same nodes, but without the union
+    let c = fingerprint(
+        "(datatype Math (Num i64) (Add Math Math))
+         (Add (Num 1) (Num 2))
+         (Add (Num 2) (Num 1))",
+    );
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+}
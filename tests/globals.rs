@@ -0,0 +1,16 @@
+use egglog::*;
+
+#[test]
+fn test_global_sort() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (let x (Num 1))
+             (let y 2.5)",
+        )
+        .unwrap();
+    assert_eq!(egraph.global_sort("x".into()), Some("Math".into()));
+    assert_eq!(egraph.global_sort("y".into()), Some("f64".into()));
+    assert_eq!(egraph.global_sort("z".into()), None);
+}
@@ -0,0 +1,24 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use egglog::*;
+
+#[test]
+fn test_on_command_sees_state_after_command() {
+    let sizes = Rc::new(RefCell::new(vec![]));
+    let mut egraph = EGraph::default();
+    let hook_sizes = sizes.clone();
+    egraph.on_command(Box::new(move |_command, egraph| {
+        hook_sizes.borrow_mut().push(egraph.num_tuples())
+    }));
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (Num 1)
+             (Num 2)",
+        )
+        .unwrap();
+    let sizes = sizes.borrow();
+    assert!(!sizes.is_empty());
+    assert_eq!(sizes.last(), Some(&2));
+}
@@ -0,0 +1,54 @@
+use egglog::*;
+
+#[test]
+fn test_include_paths() {
+    let mut egraph = EGraph::default();
+    assert!(egraph
+        .parse_and_run_program(r#"(include "path.egg")"#)
+        .is_err());
+    egraph.add_include_path("tests");
+    egraph
+        .parse_and_run_program(r#"(include "path.egg")"#)
+        .unwrap();
+}
+
+#[test]
+fn test_missing_include_is_an_error() {
+    let mut egraph = EGraph::default();
+    match egraph.parse_and_run_program(r#"(include "tests/does-not-exist.egg")"#) {
+        Err(Error::IoError(path, _)) => assert_eq!(path.to_str(), Some("tests/does-not-exist.egg")),
+        res => panic!("Expected an IO error, got {res:?}"),
+    }
+}
+
+#[test]
+fn test_include_always() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(r#"(include "tests/path.egg")"#)
+        .unwrap();
+    // path.egg declares its relations, so including it again for real fails
+    assert!(egraph
+        .parse_and_run_program(r#"(include-always "tests/path.egg")"#)
+        .is_err());
+}
+
+#[test]
+fn test_include_after_parse_error() {
+    let dir = std::env::temp_dir().join(format!("egglog-include-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("fixed.egg");
+    let include = format!("(include \"{}\")", file.display());
+
+    let mut egraph = EGraph::default();
+    std::fs::write(&file, "(relation edge (i64 i64)").unwrap();
+    assert!(matches!(
+        egraph.parse_and_run_program(&include),
+        Err(Error::ParseError(_))
+    ));
+
+    // the failed include doesn't count, so the fixed file is included
+    std::fs::write(&file, "(relation edge (i64 i64)) (edge 1 2)").unwrap();
+    egraph.parse_and_run_program(&include).unwrap();
+    egraph.parse_and_run_program("(check (edge 1 2))").unwrap();
+}
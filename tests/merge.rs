@@ -0,0 +1,101 @@
+use egglog::{ast::*, *};
+
+fn lattice(name: &str, sort: &str, merge: Expr) -> FunctionDecl {
+    FunctionDecl {
+        name: name.into(),
+        schema: Schema::new(vec!["i64".into()], sort.into()),
+        merge: Some(merge),
+        merge_action: vec![],
+        default: None,
+        cost: None,
+        unextractable: false,
+    }
+}
+
+#[test]
+fn test_merge_constructors() {
+    assert_eq!(min_merge("i64".into()).to_string(), "(min old new)");
+    assert_eq!(max_merge("f64".into()).to_string(), "(max old new)");
+    assert_eq!(
+        min_merge("String".into()).to_string(),
+        "(min-string old new)"
+    );
+}
+
+#[test]
+fn test_max_merge_is_a_lattice() {
+    let mut egraph = EGraph::default();
+    egraph
+        .add_function(lattice("hi", "i64", max_merge("i64".into())))
+        .unwrap();
+    egraph
+        .add_function(lattice("lo", "String", min_merge("String".into())))
+        .unwrap();
+    egraph
+        .parse_and_run_program(
+            "(set (hi 0) 3)
+             (set (hi 0) 7)
+             (set (hi 0) 5)
+             (check (= (hi 0) 7))
+             (set (lo 0) \"b\")
+             (set (lo 0) \"a\")
+             (set (lo 0) \"c\")
+             (check (= (lo 0) \"a\"))",
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_eq_function_with_merge_makes_no_default() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (function f (i64) Math :merge old)
+             (set (f 0) (Num 0))
+             (set (f 0) (Num 1))
+             (check (= (f 0) (Num 0)))",
+        )
+        .unwrap();
+    // unlike a constructor of a datatype with a `:merge`, the call doesn't make a new e-class
+    let result = egraph.parse_and_run_program("(f 1)");
+    assert!(matches!(result, Err(Error::NotFoundError(_))));
+}
+
+#[test]
+fn test_merge_order_compares_stored_outputs() {
+    let mut egraph = EGraph::default();
+    egraph.check_merge_order = true;
+    // swapping `old` and `new` picks the other value, but `on_merge` makes them equal
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (function f () Math :merge old :on_merge ((union old new)))
+             (set (f) (Num 0))
+             (set (f) (Num 1))
+             (check (= (f) (Num 1)))",
+        )
+        .unwrap();
+    let result = egraph.parse_and_run_program(
+        "(function g () Math :merge old)
+         (set (g) (Num 2))
+         (set (g) (Num 3))",
+    );
+    assert!(matches!(result, Err(Error::MergeOrderError(name, _, _)) if name.as_str() == "g"));
+}
+
+#[test]
+fn test_merge_order_error_during_rebuild() {
+    let mut egraph = EGraph::default();
+    egraph.check_merge_order = true;
+    // the rows of `h` only merge when the rebuild after the union runs
+    let result = egraph.parse_and_run_program(
+        "(datatype Math (Num i64))
+         (function h (Math) i64 :merge (- old new))
+         (set (h (Num 0)) 5)
+         (set (h (Num 1)) 2)
+         (rule ((= x (Num 0)) (= y (Num 1))) ((union x y)))
+         (run 2)",
+    );
+    assert!(matches!(result, Err(Error::MergeOrderError(name, _, _)) if name.as_str() == "h"));
+}
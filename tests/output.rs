@@ -0,0 +1,42 @@
+use egglog::*;
+
+fn output_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("egglog-output-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let _ = std::fs::remove_file(dir.join("out.txt"));
+    dir
+}
+
+#[test]
+fn test_output_appends_one_expr_per_line() {
+    let dir = output_dir("append");
+    let mut egraph = EGraph::default();
+    egraph.fact_directory = Some(dir.clone());
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (output :append \"out.txt\" (Num 1))
+             (output :append \"out.txt\" (Add (Num 1) (Num 2)))",
+        )
+        .unwrap();
+    let written = std::fs::read_to_string(dir.join("out.txt")).unwrap();
+    assert_eq!(written, "(Num 1)\n(Add (Num 1) (Num 2))\n");
+
+    egraph
+        .parse_and_run_program("(output :truncate \"out.txt\" (Num 3))")
+        .unwrap();
+    let written = std::fs::read_to_string(dir.join("out.txt")).unwrap();
+    assert_eq!(written, "(Num 3)\n");
+}
+
+#[test]
+fn test_output_to_stdout() {
+    let mut egraph = EGraph::default();
+    let msgs = egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (output :stdout (Num 1) (Num 2))",
+        )
+        .unwrap();
+    assert_eq!(msgs, vec!["(Num 1)".to_string(), "(Num 2)".to_string()]);
+}
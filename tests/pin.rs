@@ -0,0 +1,35 @@
+use egglog::{ast::*, *};
+
+fn class_of(egraph: &mut EGraph, name: &str) -> Id {
+    let (_sort, value) = egraph
+        .eval_expr(&Expr::Var(DUMMY_SPAN, name.into()), None, false)
+        .unwrap();
+    egraph.find(Id::from(value.bits as usize))
+}
+
+#[test]
+fn test_union_into_pinned_class_keeps_its_id() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (let a (Num 1))
+             (let b (Add (Num 0) (Num 1)))
+             (let c (Num 2))
+             (pin b)",
+        )
+        .unwrap();
+    let pinned = class_of(&mut egraph, "b");
+
+    // b was made after a, so without the pin the union would keep a's id
+    egraph
+        .parse_and_run_program("(union a b) (union c a)")
+        .unwrap();
+    assert_eq!(class_of(&mut egraph, "a"), pinned);
+    assert_eq!(class_of(&mut egraph, "c"), pinned);
+
+    // pinning another member of the class doesn't move the pin
+    egraph.parse_and_run_program("(pin c)").unwrap();
+    assert_eq!(class_of(&mut egraph, "c"), pinned);
+    assert_eq!(class_of(&mut egraph, "b"), pinned);
+}
@@ -0,0 +1,59 @@
+use egglog::*;
+
+// This file tests resetting an e-graph to a prelude.
+
+#[test]
+fn test_reset_to_prelude() {
+    let mut desugarer = EGraph::default();
+    let program = desugarer
+        .parse_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (rewrite (Add a b) (Add b a))
+             (let one (Num 1))",
+        )
+        .unwrap();
+    let prelude = desugarer
+        .process_commands(program, CompilerPassStop::Desugar)
+        .unwrap();
+
+    let mut egraph = EGraph::with_prelude(prelude).unwrap();
+    egraph
+        .parse_and_run_program("(let e (Add one (Num 2))) (run 1) (check (= e (Add (Num 2) one)))")
+        .unwrap();
+
+    egraph.reset_to_prelude();
+    assert!(egraph.parse_and_run_program("(check e)").is_err());
+    egraph
+        .parse_and_run_program("(let e (Add one (Num 3))) (check (= one (Num 1)))")
+        .unwrap();
+}
+
+#[test]
+fn test_let_after_prelude_let() {
+    let mut desugarer = EGraph::default();
+    let program = desugarer
+        .parse_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (let one (Num 1))
+             (let two (Add one one))",
+        )
+        .unwrap();
+    let prelude = desugarer
+        .process_commands(program, CompilerPassStop::Desugar)
+        .unwrap();
+
+    let mut egraph = EGraph::with_prelude(prelude).unwrap();
+    // the prelude's temporaries were named by another desugarer, so new ones must not reuse them
+    egraph
+        .parse_and_run_program(
+            "(let three (Num 3))
+             (let four (Add two two))
+             (check (= four (Add (Add (Num 1) (Num 1)) two)))
+             (check (= three (Num 3)))",
+        )
+        .unwrap();
+    egraph.reset_to_prelude();
+    egraph
+        .parse_and_run_program("(let three (Num 3)) (check (= two (Add one one)))")
+        .unwrap();
+}
@@ -0,0 +1,65 @@
+use egglog::{ast::Symbol, *};
+
+struct Double {
+    i64: ArcSort,
+}
+
+impl PrimitiveLike for Double {
+    fn name(&self) -> Symbol {
+        "double".into()
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [t] if t.name() == self.i64.name() => Some(self.i64.clone()),
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        Some(Value::from((values[0].bits as i64) * 2))
+    }
+}
+
+fn i64_sort(egraph: &EGraph) -> ArcSort {
+    egraph
+        .type_info()
+        .sorts()
+        .find(|sort| sort.name() == "i64".into())
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn test_custom_primitive_in_rule() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program("(datatype Math (Num i64))")
+        .unwrap();
+    let i64 = i64_sort(&egraph);
+    egraph.add_primitive(Double { i64 }).unwrap();
+    egraph
+        .parse_and_run_program(
+            "(relation doubled (i64))
+             (rule ((Num n)) ((doubled (double n))))
+             (Num 21)
+             (run 1)
+             (check (doubled 42))
+             (check (= (double 4) 8))",
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_custom_primitive_name_taken() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program("(function double (i64) i64)")
+        .unwrap();
+    let i64 = i64_sort(&egraph);
+    assert!(egraph.add_primitive(Double { i64 }).is_err());
+    // the function is still what the name refers to
+    egraph
+        .parse_and_run_program("(set (double 1) 5) (check (= (double 1) 5))")
+        .unwrap();
+}
@@ -0,0 +1,45 @@
+use egglog::*;
+
+#[test]
+fn test_print_sort_prints_each_function_of_the_sort() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64) (Add Math Math))
+             (function size (Math) i64 :merge (min old new))
+             (set (size (Add (Num 1) (Num 1))) 3)",
+        )
+        .unwrap();
+    let msgs = egraph.parse_and_run_program("(print-sort Math)").unwrap();
+    assert_eq!(msgs[0], "Add:");
+    assert!(msgs[1].contains("(Add (Num 1) (Num 1))"));
+    assert_eq!(msgs[2], "Num:");
+    assert!(msgs[3].contains("(Num 1)"));
+    assert_eq!(msgs.len(), 4);
+
+    let msgs = egraph.parse_and_run_program("(print-sort i64)").unwrap();
+    assert_eq!(msgs[0], "size:");
+    assert!(msgs[1].contains("-> 3"));
+    assert_eq!(msgs.len(), 2);
+}
+
+#[test]
+fn test_print_size_lists_functions_by_size() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(relation edge (i64 i64))
+             (relation path (i64 i64))
+             (relation start (i64))
+             (edge 1 2)
+             (edge 2 3)
+             (start 1)
+             (rule ((edge a b)) ((path a b)))
+             (rule ((path a b) (edge b c)) ((path a c)))
+             (run 3)",
+        )
+        .unwrap();
+    let msgs = egraph.parse_and_run_program("(print-size)").unwrap();
+    // largest first, ties broken by name
+    assert_eq!(msgs, vec!["path: 3", "edge: 2", "start: 1"]);
+}
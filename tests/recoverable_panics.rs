@@ -0,0 +1,29 @@
+use egglog::*;
+
+#[test]
+fn test_first_panic_stops_the_run() {
+    let mut egraph = EGraph::default();
+    egraph.recoverable_panics = true;
+    egraph
+        .parse_and_run_program(
+            "(relation edge (i64))
+             (relation seen (i64))
+             (edge 1)
+             (edge 2)
+             (rule ((edge x)) ((seen x) (panic \"found an edge\")))",
+        )
+        .unwrap();
+    let Err(Error::UserPanic(msg, bindings)) = egraph.parse_and_run_program("(run 3)") else {
+        panic!("expected a user panic");
+    };
+    assert_eq!(msg, "found an edge");
+    // only the first match ran its actions, and the panic reports its binding of `x`
+    assert_eq!(egraph.function_size(Some("seen".into())).unwrap(), 1);
+    let x = if egraph.parse_and_run_program("(check (seen 1))").is_ok() {
+        1i64
+    } else {
+        2
+    };
+    assert!(bindings.contains(&Value::from(x)));
+    assert!(!bindings.contains(&Value::from(3 - x)));
+}
@@ -0,0 +1,266 @@
+use egglog::*;
+
+#[test]
+fn test_serialization_truncated() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (Num 1)
+             (Num 2)",
+        )
+        .unwrap();
+    let limited = SerializeConfig {
+        max_calls_per_function: Some(1),
+        ..SerializeConfig::default()
+    };
+    assert!(egraph.serialization_truncated(&limited));
+    assert!(!egraph.serialization_truncated(&SerializeConfig::default()));
+}
+
+#[test]
+fn test_serialize_temporary_functions() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (declare x Math)
+             (union x (Num 1))",
+        )
+        .unwrap();
+    let has_temporary = |config: SerializeConfig| {
+        egraph
+            .serialize(config)
+            .nodes
+            .values()
+            .any(|node| node.op.starts_with('v'))
+    };
+    assert!(!has_temporary(SerializeConfig::default()));
+    assert!(has_temporary(SerializeConfig {
+        include_temporary_functions: true,
+        ..SerializeConfig::default()
+    }));
+}
+
+#[test]
+fn test_serialized_json() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (Num 1)",
+        )
+        .unwrap();
+    let json = egraph
+        .to_serialized_json(SerializeConfig::default())
+        .unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed["nodes"].as_object().unwrap().len(), 2);
+}
+
+#[test]
+fn test_serialize_fresh_prefix() {
+    let mut egraph = EGraph::default();
+    egraph.set_fresh_prefix_for_desugaring("tmp");
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (function v0___ () Math)
+             (set (v0___) (Num 1))
+             (declare x Math)
+             (union x (Num 2))",
+        )
+        .unwrap();
+    let ops: Vec<String> = egraph
+        .serialize(SerializeConfig::default())
+        .nodes
+        .values()
+        .map(|node| node.op.clone())
+        .collect();
+    assert!(ops.contains(&"v0___".to_string()));
+    assert!(!ops.iter().any(|op| op.starts_with("tmp")));
+}
+
+#[test]
+fn test_serialize_unextractable_functions() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (function Hidden (Math) Math :unextractable)
+             (Hidden (Num 1))",
+        )
+        .unwrap();
+    let has_hidden = |config: SerializeConfig| {
+        egraph
+            .serialize(config)
+            .nodes
+            .values()
+            .any(|node| node.op == "Hidden")
+    };
+    assert!(has_hidden(SerializeConfig::default()));
+    assert!(!has_hidden(SerializeConfig {
+        include_unextractable_functions: false,
+        ..SerializeConfig::default()
+    }));
+}
+
+#[test]
+fn test_serialize_functions() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (function best (Math) i64 :merge (min old new) :default 100)
+             (set (best (Num 1)) 3)",
+        )
+        .unwrap();
+    let functions = egraph.serialize_functions(&SerializeConfig::default());
+    let best = functions.iter().find(|f| f.name == "best").unwrap();
+    assert_eq!(
+        best,
+        &SerializedFunction {
+            name: "best".into(),
+            merge: Some("(min old new)".into()),
+            default: Some("100".into()),
+            is_datatype: false,
+        }
+    );
+    let num = functions.iter().find(|f| f.name == "Num").unwrap();
+    assert!(num.is_datatype && num.merge.is_none());
+
+    let json = serde_json::to_value(best).unwrap();
+    assert_eq!(json["merge"], "(min old new)");
+
+    // a function whose only row was deleted has no live calls to describe
+    egraph
+        .parse_and_run_program("(delete (best (Num 1)))")
+        .unwrap();
+    let functions = egraph.serialize_functions(&SerializeConfig::default());
+    assert!(functions.iter().all(|f| f.name != "best"));
+    assert!(functions.iter().any(|f| f.name == "Num"));
+}
+
+#[test]
+fn test_to_dot() {
+    let program = "(datatype Math (Num i64) (Add Math Math))
+                   (rewrite (Add a b) (Add b a))
+                   (rewrite (Add a (Num 0)) a)
+                   (Add (Num 1) (Num 0))
+                   (run 3)";
+    let dot = |program: &str| {
+        let mut egraph = EGraph::default();
+        egraph.parse_and_run_program(program).unwrap();
+        egraph.to_dot(SerializeConfig::default())
+    };
+    let rendered = dot(program);
+    assert!(rendered.starts_with("digraph egraph {\n  compound=true\n"));
+    assert!(rendered.ends_with("}\n"));
+    // both Adds are merged with (Num 1), leaving (Num 0) as the only other e-class with calls
+    assert_eq!(rendered.matches("subgraph cluster_").count(), 2);
+    assert_eq!(rendered.matches("label=\"Add\"").count(), 2);
+    assert!(rendered.contains("[label=\"1\", shape=plaintext]"));
+    assert!(rendered.contains("[label=\"0\", shape=plaintext]"));
+    // each Add has two inputs, each Num one
+    assert_eq!(rendered.matches(" -> ").count(), 6);
+    assert_eq!(rendered, dot(program));
+}
+
+#[test]
+fn test_table_json() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(relation edge (i64 String))
+             (edge 1 \"a\")
+             (edge 2 \"b\")
+             (edge 3 \"c\")",
+        )
+        .unwrap();
+    let json = egraph.table_json("edge".into(), 10).unwrap();
+    let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+    let rows: Vec<(Vec<String>, String)> = rows
+        .iter()
+        .map(|row| {
+            let inputs = row["inputs"].as_array().unwrap();
+            (
+                inputs
+                    .iter()
+                    .map(|v| v.as_str().unwrap().to_string())
+                    .collect(),
+                row["output"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            (vec!["1".into(), "\"a\"".into()], "()".into()),
+            (vec!["2".into(), "\"b\"".into()], "()".into()),
+            (vec!["3".into(), "\"c\"".into()], "()".into()),
+        ]
+    );
+
+    // the rows read back in as the same relation
+    let mut copy = EGraph::default();
+    copy.parse_and_run_program("(relation edge (i64 String))")
+        .unwrap();
+    for (inputs, _output) in &rows {
+        copy.parse_and_run_program(&format!("(edge {})", inputs.join(" ")))
+            .unwrap();
+    }
+    assert_eq!(copy.table_json("edge".into(), 10).unwrap(), json);
+
+    assert_eq!(
+        egraph
+            .table_json("edge".into(), 1)
+            .unwrap()
+            .matches("inputs")
+            .count(),
+        1
+    );
+    assert!(egraph.table_json("path".into(), 10).is_err());
+}
+
+#[test]
+fn test_serialize_container_elements() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (sort MathVec (Vec Math))
+             (function f () MathVec)
+             (set (f) (vec-of (Num 1) (Num 2) (Num 3)))",
+        )
+        .unwrap();
+    let element_ops = |config: SerializeConfig| {
+        let graph = egraph.serialize(config);
+        let container = graph
+            .nodes
+            .values()
+            .find(|node| node.op == "MathVec")
+            .unwrap();
+        let elements: Vec<_> = container
+            .children
+            .iter()
+            .map(|child| &graph.nodes[child])
+            .collect();
+        // each element is in its own e-class
+        let classes: std::collections::HashSet<_> =
+            elements.iter().map(|node| &node.eclass).collect();
+        assert_eq!(classes.len(), 3);
+        let mut ops: Vec<String> = elements.iter().map(|node| node.op.clone()).collect();
+        ops.sort();
+        ops
+    };
+    assert_eq!(
+        element_ops(SerializeConfig::default()),
+        ["Num", "Num", "Num"]
+    );
+    // only one call of `Num` is included, so the other two e-classes get placeholders
+    let limited = SerializeConfig {
+        max_calls_per_function: Some(1),
+        ..SerializeConfig::default()
+    };
+    assert_eq!(element_ops(limited), ["Math", "Math", "Num"]);
+}
@@ -0,0 +1,18 @@
+use egglog::*;
+
+#[test]
+fn test_function_size() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(relation edge (i64 i64))
+             (edge 1 2)
+             (edge 2 3)
+             (edge 3 4)
+             (edge 1 2)",
+        )
+        .unwrap();
+    assert_eq!(egraph.function_size(Some("edge".into())).unwrap(), 3);
+    assert_eq!(egraph.function_size(None).unwrap(), 3);
+    assert!(egraph.function_size(Some("path".into())).is_err());
+}
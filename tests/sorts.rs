@@ -0,0 +1,100 @@
+use std::{any::Any, sync::Arc};
+
+use egglog::{
+    ast::{Expr, Symbol},
+    sort::Sort,
+    *,
+};
+
+#[test]
+fn test_sort_kinds() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (sort IntVec (Vec i64))",
+        )
+        .unwrap();
+    let sorts = egraph.sorts();
+    let kind = |name: &str| {
+        let sort = sorts.iter().find(|sort| sort.name == name.into()).unwrap();
+        (sort.is_eq_sort, sort.is_container_sort)
+    };
+    assert_eq!(kind("i64"), (false, false));
+    assert_eq!(kind("String"), (false, false));
+    assert_eq!(kind("Math"), (true, false));
+    assert_eq!(kind("IntVec"), (false, true));
+    assert!(sorts
+        .windows(2)
+        .all(|w| w[0].name.as_str() < w[1].name.as_str()));
+}
+
+// a sort whose primitive needs the `Math` datatype
+#[derive(Debug)]
+struct HandleSort;
+
+impl Sort for HandleSort {
+    fn name(&self) -> Symbol {
+        "Handle".into()
+    }
+
+    fn as_arc_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync + 'static> {
+        self
+    }
+
+    fn register_primitives(self: Arc<Self>, info: &mut TypeInfo) {
+        let find = |name: &str| {
+            info.sorts()
+                .find(|sort| sort.name() == name.into())
+                .unwrap()
+                .clone()
+        };
+        let (math, unit) = (find("Math"), find(UNIT_SYM));
+        info.add_primitive(SameMath { math, unit });
+    }
+
+    fn make_expr(&self, _egraph: &EGraph, _value: Value) -> (Cost, Expr) {
+        unreachable!("no values of this sort are made")
+    }
+}
+
+struct SameMath {
+    math: ArcSort,
+    unit: ArcSort,
+}
+
+impl PrimitiveLike for SameMath {
+    fn name(&self) -> Symbol {
+        "same-math".into()
+    }
+
+    fn accept(&self, types: &[ArcSort]) -> Option<ArcSort> {
+        match types {
+            [a, b] if a.name() == self.math.name() && b.name() == self.math.name() => {
+                Some(self.unit.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&self, values: &[Value]) -> Option<Value> {
+        (values[0] == values[1]).then(Value::unit)
+    }
+}
+
+#[test]
+fn test_primitives_registered_after_later_sorts() {
+    let mut egraph = EGraph::default();
+    // `Math` isn't declared yet, but it is by the time the primitive is registered
+    egraph.add_arcsort(Arc::new(HandleSort)).unwrap();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (sort MathVec (Vec Math))
+             (union (Num 1) (Num 2))
+             (check (same-math (Num 1) (Num 2)))
+             (fail (check (same-math (Num 1) (Num 3))))
+             (check (= (vec-length (vec-of (Num 1) (Num 3))) 2))",
+        )
+        .unwrap();
+}
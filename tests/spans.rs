@@ -0,0 +1,40 @@
+use egglog::{ast::*, *};
+
+#[test]
+fn test_unbound_variable_reports_its_line() {
+    let mut egraph = EGraph::default();
+    let err = egraph
+        .parse_and_run_program(
+            "(relation edge (i64 i64))
+(relation path (i64 i64))
+(rule ((edge x y))
+      ((path x z)))",
+        )
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Unbound symbol z at 4:16");
+}
+
+#[test]
+fn test_no_matching_primitive_reports_its_line() {
+    let mut egraph = EGraph::default();
+    let err = egraph
+        .parse_and_run_program(
+            "(relation edge (i64 i64))
+(edge 1 2)
+(let x (+ 1 \"a\"))",
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "No matching primitive for: (+ i64 String) at 3:8"
+    );
+}
+
+#[test]
+fn test_built_exprs_have_no_location() {
+    let mut egraph = EGraph::default();
+    let err = egraph
+        .eval_expr(&Expr::Var(DUMMY_SPAN, "nope".into()), None, false)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "Errors:\nUnbound symbol nope");
+}
@@ -0,0 +1,33 @@
+use egglog::*;
+
+#[test]
+fn test_ruleset_stats() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(function f (i64) i64)
+             (set (f 0) 0)
+             (ruleset count)
+             (rule ((= (f n) m) (< n 10)) ((set (f (+ n 1)) (+ m 1))) :ruleset count)
+             (rule ((f n)) ((f n)))",
+        )
+        .unwrap();
+    let stats = egraph.ruleset_stats("count".into()).unwrap();
+    assert_eq!(stats.rules.len(), 1);
+    assert_eq!(stats.total_matches(), 0);
+
+    let mut last = 0;
+    for _ in 0..5 {
+        egraph.parse_and_run_program("(run count 1)").unwrap();
+        let matches = egraph
+            .ruleset_stats("count".into())
+            .unwrap()
+            .total_matches();
+        assert!(matches > last);
+        last = matches;
+    }
+
+    // the rule in the default ruleset didn't run
+    assert_eq!(egraph.ruleset_stats("".into()).unwrap().total_matches(), 0);
+    assert!(egraph.ruleset_stats("missing".into()).is_none());
+}
@@ -0,0 +1,23 @@
+use egglog::{sort::StringSort, *};
+
+#[test]
+fn test_strings_are_local_to_egraph() {
+    let program = "(function label (i64) String)
+                   (set (label 0) \"item\")
+                   (rule ((= s (label n)) (< n 500))
+                         ((set (label (+ n 1)) (+ s (to-string n)))))
+                   (run 1000)
+                   (check (= (label 3) \"item012\"))";
+    for _ in 0..10 {
+        let mut egraph = EGraph::default();
+        egraph.parse_and_run_program(program).unwrap();
+        let strings = egraph.type_info().get_sort::<StringSort>();
+        // one string per label, plus the `to-string` pieces and the literals
+        assert!(strings.num_strings() >= 1000);
+        assert!(strings.num_strings() < 1100);
+    }
+    // a new e-graph starts from an empty table no matter how many strings came before
+    let egraph = EGraph::default();
+    let strings = egraph.type_info().get_sort::<StringSort>();
+    assert_eq!(strings.num_strings(), 0);
+}
@@ -0,0 +1,30 @@
+use egglog::*;
+
+#[test]
+fn test_type_info_accessors() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program("(datatype Math (Num i64) (Add Math Math))")
+        .unwrap();
+    let type_info = egraph.type_info();
+
+    assert!(type_info.sorts().any(|sort| sort.name() == "Math".into()));
+    assert!(!type_info.primitives_named("+".into()).is_empty());
+    assert!(type_info
+        .primitives_named("not-a-primitive".into())
+        .is_empty());
+
+    let add = type_info.func_type("Add".into()).unwrap();
+    assert_eq!(add.input.len(), 2);
+    assert_eq!(add.output.name(), "Math".into());
+    assert!(type_info.func_type("Sub".into()).is_none());
+}
+
+#[test]
+fn test_cyclic_sort() {
+    let mut egraph = EGraph::default();
+    let err = egraph
+        .parse_and_run_program("(sort Nested (Set Nested))")
+        .unwrap_err();
+    assert!(err.to_string().contains("refers to itself"), "{err}");
+}
@@ -0,0 +1,24 @@
+use egglog::*;
+
+#[test]
+fn test_take_warnings() {
+    let mut egraph = EGraph::default();
+    egraph
+        .parse_and_run_program(
+            "(datatype Math (Num i64))
+             (relation matched (i64))
+             (rule ((Num x)) ((matched x)))",
+        )
+        .unwrap();
+    assert!(egraph.take_warnings().is_empty());
+
+    egraph
+        .parse_and_run_program("(rule ((Num x) (= x 1) (= x 2)) ((matched x)) :name \"unsat\")")
+        .unwrap();
+    let warnings = egraph.take_warnings();
+    assert!(!warnings.is_empty());
+    assert!(warnings
+        .iter()
+        .all(|warning| warning.contains("unsat") && warning.contains("can never match")));
+    assert!(egraph.take_warnings().is_empty());
+}